@@ -1,19 +1,174 @@
+use super::common;
 use clap::{self, Arg, ArgMatches};
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use turbogrep::dir_walker::WalkerOptions;
+use turbogrep::file_changes::{
+    CaseSensitivity, ContextLines, MatchOptions, WantedChanges, WordChars,
+};
+use turbogrep::file_io::HardLinkPolicy;
+use turbogrep::rules::RuleSet;
 
 type ParseResult<T> = Result<T, ()>;
 
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub enum OutputFormat {
+    /// The colored `old -> new` listing of every changed line.
+    #[default]
+    Preview,
+    /// A unified diff that can be piped into `git apply` or `patch`.
+    Diff,
+    /// One JSON object per line for every file with matches, followed by a
+    /// summary object.
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "preview" => Ok(Self::Preview),
+            "diff" => Ok(Self::Diff),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("unknown output format \"{}\"", format)),
+        }
+    }
+}
+
 #[derive(Default, Debug)]
 pub struct UserInput {
     pub pattern_string: String,
     pub term: String,
-    pub replace: bool,
     pub replacement: Option<String>,
+    pub paths: Vec<String>,
+    pub globs: Vec<String>,
+    pub excludes: Vec<String>,
     pub dry_run: bool,
     pub silent: bool,
+    pub regex: bool,
+    pub interactive: bool,
+    pub no_ignore: bool,
+    pub threads: usize,
+    pub format: OutputFormat,
+    pub hard_links: HardLinkPolicy,
+    pub binary: bool,
+    pub after_context: Option<usize>,
+    pub before_context: Option<usize>,
+    pub context: Option<usize>,
+    pub ignore_case: bool,
+    pub smart_case: bool,
+    pub word: bool,
+    pub identifier: Option<String>,
+    pub word_chars: Option<String>,
+    pub preserve_case: bool,
+    pub multiline: bool,
+    pub rename_paths: bool,
+    pub rules: Option<String>,
+    pub stdin: bool,
+    pub stdout: bool,
+}
+
+impl UserInput {
+    pub fn wanted_changes(&self) -> WantedChanges {
+        let old = self.term.to_string();
+        let new = match &self.replacement {
+            Some(replacement) => replacement.to_string(),
+            // without a replacement, every match is "replaced" by itself
+            None if self.regex => "$0".to_string(),
+            None => old.to_string(),
+        };
+        WantedChanges::new(old, new, self.match_options())
+            .unwrap_or_else(|error| common::clap_panic(error))
+    }
+
+    /// The rules of the `--rules` file, or the single rule of the term.
+    pub fn rule_set(&self) -> io::Result<RuleSet> {
+        match &self.rules {
+            Some(rules_path) => RuleSet::read(Path::new(rules_path)),
+            None => Ok(RuleSet::from(self.wanted_changes())),
+        }
+    }
+
+    /// Whether the files are changed (unless `--dry-run` is set), which takes
+    /// a replacement or a rules file.
+    pub fn replaces(&self) -> bool {
+        self.replacement.is_some() || self.rules.is_some()
+    }
+
+    pub fn match_options(&self) -> MatchOptions {
+        let case = match (self.ignore_case, self.smart_case) {
+            (true, _) => CaseSensitivity::Insensitive,
+            (false, true) => CaseSensitivity::Smart,
+            (false, false) => CaseSensitivity::Sensitive,
+        };
+        MatchOptions {
+            regex: self.regex,
+            case,
+            word: self.word_boundaries(),
+            preserve_case: self.preserve_case,
+            multiline: self.multiline,
+        }
+    }
+
+    /// `--identifier` and `--word-chars` imply `--word`, the extra word
+    /// characters of both are combined.
+    fn word_boundaries(&self) -> Option<WordChars> {
+        if !self.word && self.identifier.is_none() && self.word_chars.is_none() {
+            return None;
+        }
+        let mut word_chars = match &self.identifier {
+            None => WordChars::default(),
+            Some(language) => WordChars::for_language(language).unwrap_or_else(|| {
+                common::clap_panic(format!("unknown language \"{}\"", language))
+            }),
+        };
+        if let Some(extra) = &self.word_chars {
+            word_chars.extra.push_str(extra);
+        }
+        Some(word_chars)
+    }
+
+    /// `-A` and `-B` take precedence over `-C`, which sets both at once.
+    pub fn context_lines(&self) -> ContextLines {
+        let default = ContextLines::default();
+        ContextLines {
+            before: self
+                .before_context
+                .or(self.context)
+                .unwrap_or(default.before),
+            after: self.after_context.or(self.context).unwrap_or(default.after),
+        }
+    }
+
+    /// The positional pattern (or the globs of the rules) and every `--glob`,
+    /// with the `--exclude` patterns turned into `!`-prefixed globs.
+    pub fn path_globs(&self, rule_set: &RuleSet) -> Vec<String> {
+        let mut globs = match self.rules {
+            Some(_) => rule_set
+                .walk_globs()
+                .into_iter()
+                .map(|glob| glob.to_string())
+                .collect(),
+            None => vec![self.pattern_string.to_string()],
+        };
+        globs.extend(self.globs.iter().cloned());
+        globs.extend(self.excludes.iter().map(|exclude| format!("!{}", exclude)));
+        globs
+    }
+
+    pub fn walker_options(&self) -> WalkerOptions {
+        WalkerOptions {
+            respect_ignore_files: !self.no_ignore,
+            threads: self.threads,
+        }
+    }
 }
 
 pub trait ClapArg<'a> {
     fn get_args<'b>() -> Vec<Arg<'a, 'b>>;
+    #[allow(clippy::result_unit_err)]
     fn from_matches(matches: &ArgMatches) -> ParseResult<Self>
     where
         Self: Sized;
@@ -25,26 +180,49 @@ impl<'a> ClapArg<'a> for UserInput {
         vec![
             Arg::with_name("expr")
                 .help("the pattern expression to match the files for")
-                .required(true)
+                .required_unless_one(&["rules", "stdin"])
                 .takes_value(true)
                 .index(1),
             Arg::with_name("term")
                 .help("the term present in the files to find")
-                .required(true)
+                .required_unless_one(&["rules", "stdin"])
                 .takes_value(true)
                 .index(2),
+            Arg::with_name("glob")
+                .help("another pattern expression to match the files for, or to skip the files and directories it matches when prefixed with ! (can be repeated)")
+                .long("glob")
+                .short("g")
+                .takes_value(true)
+                .value_name("GLOB")
+                .multiple(true)
+                .number_of_values(1)
+                .required(false),
+            Arg::with_name("exclude")
+                .help("a pattern expression for the files and directories to skip, same as --glob '!<GLOB>' (can be repeated)")
+                .long("exclude")
+                .takes_value(true)
+                .value_name("GLOB")
+                .multiple(true)
+                .number_of_values(1)
+                .required(false),
+            // giving the new term is enough to replace, the flag is only kept
+            // so that the scripts passing it still work
             Arg::with_name("replace")
-                .help("if set, allows the user to set a new term that\
-                      will replace all found instances of the term to find")
+                .help("deprecated, does nothing")
                 .long("replace")
                 .short("r")
+                .hidden(true)
                 .multiple(false)
                 .required(false),
             Arg::with_name("replacement")
                 .help("the (new) term to replace the old term with")
-                .requires("replace")
                 .takes_value(true)
                 .index(3),
+            Arg::with_name("paths")
+                .help("the directories and files to search in (defaults to the current directory)")
+                .takes_value(true)
+                .multiple(true)
+                .index(4),
             Arg::with_name("dry-run")
                 .help("if set, does not execute the final step of replacing the matching terms in the files")
                 .long("dry-run")
@@ -57,6 +235,164 @@ impl<'a> ClapArg<'a> for UserInput {
                 .long("silent")
                 .short("s")
                 .multiple(false)
+                .required(false),
+            Arg::with_name("regex")
+                .help("if set, the term is treated as a regular expression and the new term can reference its capture groups (e.g. $1, ${name})")
+                .long("regex")
+                .short("E")
+                .multiple(false)
+                .required(false),
+            Arg::with_name("ignore-case")
+                .help("if set, the term matches regardless of the case of its letters (simple case folding, each letter matching a single letter: ß matches ẞ but not SS)")
+                .long("ignore-case")
+                .short("i")
+                .overrides_with("smart-case")
+                .multiple(false)
+                .required(false),
+            Arg::with_name("smart-case")
+                .help("if set, the term matches regardless of case unless it contains an uppercase letter")
+                .long("smart-case")
+                .short("S")
+                .overrides_with("ignore-case")
+                .multiple(false)
+                .required(false),
+            Arg::with_name("word")
+                .help("if set, the term only matches whole words, i.e. when it is not preceded or followed by a letter, digit or _")
+                .long("word")
+                .short("w")
+                .multiple(false)
+                .required(false),
+            Arg::with_name("identifier")
+                .help("the term only matches whole identifiers of the given language, e.g. identifiers can contain $ in js and - in css or lisp (implies --word)")
+                .long("identifier")
+                .takes_value(true)
+                .value_name("LANG")
+                .multiple(false)
+                .required(false),
+            Arg::with_name("word-chars")
+                .help("extra characters that are part of words on top of letters, digits and _ (implies --word)")
+                .long("word-chars")
+                .takes_value(true)
+                .value_name("CHARS")
+                .multiple(false)
+                .required(false),
+            Arg::with_name("preserve-case")
+                .help("if set, every case variant of the term (foo_bar, foo-bar, fooBar, FooBar, FOO_BAR) is replaced by the same variant of the new term")
+                .long("preserve-case")
+                .conflicts_with_all(&["regex", "ignore-case", "smart-case"])
+                .multiple(false)
+                .required(false),
+            Arg::with_name("multiline")
+                .help("if set, the term is matched against the whole file instead of line by line, so the term and the new term can contain newlines (e.g. \\n with --regex)")
+                .long("multiline")
+                .short("U")
+                .multiple(false)
+                .required(false),
+            Arg::with_name("rules")
+                .help("a TOML (or YAML, for .yaml and .yml files) file with many rules to apply in one pass, each with its own old and new terms, glob, flags and description; all the positional args are then paths to search in")
+                .long("rules")
+                .takes_value(true)
+                .value_name("FILE")
+                .conflicts_with_all(&[
+                    "regex",
+                    "ignore-case",
+                    "smart-case",
+                    "word",
+                    "identifier",
+                    "word-chars",
+                    "preserve-case",
+                    "multiline",
+                    "rename-paths",
+                ])
+                .multiple(false)
+                .required(false),
+            Arg::with_name("stdin")
+                .help("if set, replaces the term in the standard input and prints the result to the standard output, like sed; the positional args are then the old and the new term only")
+                .long("stdin")
+                .conflicts_with_all(&["stdout", "interactive", "format", "json", "rename-paths"])
+                .multiple(false)
+                .required(false),
+            Arg::with_name("stdout")
+                .help("if set, prints the contents of every file searched, with the term replaced, to the standard output instead of writing the files")
+                .long("stdout")
+                .conflicts_with_all(&["interactive", "format", "json", "rename-paths"])
+                .multiple(false)
+                .required(false),
+            Arg::with_name("rename-paths")
+                .help("if set, also renames the files and directories whose names contain the term, deepest paths first")
+                .long("rename-paths")
+                .conflicts_with("interactive")
+                .multiple(false)
+                .required(false),
+            Arg::with_name("interactive")
+                .help("if set, asks for confirmation before applying each hunk")
+                .long("interactive")
+                .conflicts_with("dry-run")
+                .multiple(false)
+                .required(false),
+            Arg::with_name("no-ignore")
+                .help("if set, does not skip the files and directories listed in .gitignore, .ignore and .turbogrepignore files")
+                .long("no-ignore")
+                .multiple(false)
+                .required(false),
+            Arg::with_name("threads")
+                .help("the number of threads used to search and replace in files (defaults to the number of CPUs)")
+                .long("threads")
+                .short("j")
+                .takes_value(true)
+                .value_name("N")
+                .multiple(false)
+                .required(false),
+            Arg::with_name("format")
+                .help("the format the changes are printed in, \"diff\" prints a unified diff that can be applied with git apply or patch and \"json\" prints one JSON object per file")
+                .long("format")
+                .short("f")
+                .takes_value(true)
+                .possible_values(&["preview", "diff", "json"])
+                .conflicts_with("interactive")
+                .multiple(false)
+                .required(false),
+            Arg::with_name("json")
+                .help("prints the changes as one JSON object per file, same as --format json")
+                .long("json")
+                .conflicts_with_all(&["format", "interactive"])
+                .multiple(false)
+                .required(false),
+            Arg::with_name("hard-links")
+                .help("how files with several hard links are written, \"replace\" gives the path a new file and \"write-through\" changes the file shared by all links (symlinks are always written through)")
+                .long("hard-links")
+                .takes_value(true)
+                .possible_values(&["replace", "write-through"])
+                .multiple(false)
+                .required(false),
+            Arg::with_name("binary")
+                .help("if set, also searches binary files (files with NUL bytes) instead of skipping them")
+                .long("binary")
+                .multiple(false)
+                .required(false),
+            Arg::with_name("after-context")
+                .help("the number of lines to show after each changed line")
+                .long("after-context")
+                .short("A")
+                .takes_value(true)
+                .value_name("NUM")
+                .multiple(false)
+                .required(false),
+            Arg::with_name("before-context")
+                .help("the number of lines to show before each changed line")
+                .long("before-context")
+                .short("B")
+                .takes_value(true)
+                .value_name("NUM")
+                .multiple(false)
+                .required(false),
+            Arg::with_name("context")
+                .help("the number of lines to show before and after each changed line (defaults to 2)")
+                .long("context")
+                .short("C")
+                .takes_value(true)
+                .value_name("NUM")
+                .multiple(false)
                 .required(false)
         ]
     }
@@ -71,35 +407,96 @@ impl<'a> ClapArg<'a> for UserInput {
         vec![
             |mut this, matches| {
                 let arg_name = "expr";
-                this.pattern_string = matches
-                    .value_of(arg_name)
-                    .ok_or_else(|| panic_because_of_bad_parse())
-                    .unwrap()
-                    .to_string();
+                this.pattern_string = match matches.value_of(arg_name) {
+                    Some(val) => val.to_string(),
+                    None if matches.is_present("rules") || matches.is_present("stdin") => {
+                        String::new()
+                    }
+                    None => panic_because_of_bad_parse(),
+                };
                 this
             },
             |mut this, matches| {
                 let arg_name = "term";
-                this.term = matches
-                    .value_of(arg_name)
-                    .ok_or_else(|| panic_because_of_bad_parse())
-                    .unwrap()
-                    .to_string();
+                this.term = match matches.value_of(arg_name) {
+                    Some(val) => val.to_string(),
+                    None if matches.is_present("rules") || matches.is_present("stdin") => {
+                        String::new()
+                    }
+                    None => panic_because_of_bad_parse(),
+                };
                 this
             },
             |mut this, matches| {
-                let arg_name = "replace";
-                this.replace = matches.is_present(arg_name);
+                let arg_name = "replacement";
+                this.replacement = matches.value_of(arg_name).map(|val| val.to_string());
                 this
             },
             |mut this, matches| {
-                let arg_name = "replacement";
-                this.replacement = match matches.value_of(arg_name) {
-                    Some(val) => Some(val.to_string()),
-                    None => None,
+                let arg_name = "paths";
+                this.paths = match matches.values_of(arg_name) {
+                    None => vec![".".to_string()],
+                    Some(vals) => vals.map(|val| val.to_string()).collect(),
                 };
                 this
             },
+            |mut this, matches| {
+                let arg_name = "rules";
+                this.rules = matches.value_of(arg_name).map(|val| val.to_string());
+                // the rules have their own terms, so every positional arg is a path
+                if this.rules.is_some() {
+                    let paths = ["expr", "term", "replacement", "paths"]
+                        .iter()
+                        .flat_map(|arg_name| values_of_strings(matches, arg_name))
+                        .collect::<Vec<String>>();
+                    if !paths.is_empty() {
+                        this.paths = paths;
+                    }
+                    this.pattern_string = String::new();
+                    this.term = String::new();
+                    this.replacement = None;
+                }
+                this
+            },
+            |mut this, matches| {
+                let arg_name = "stdin";
+                this.stdin = matches.is_present(arg_name);
+                // without files there is no pattern expression, the positional
+                // args are only the terms (or nothing with a rules file)
+                if this.stdin {
+                    let args = ["expr", "term", "replacement", "paths"]
+                        .iter()
+                        .flat_map(|arg_name| values_of_strings(matches, arg_name))
+                        .collect::<Vec<String>>();
+                    match (&this.rules, args.as_slice()) {
+                        (Some(_), []) => {}
+                        (None, [term, replacement]) => {
+                            this.term = term.to_string();
+                            this.replacement = Some(replacement.to_string());
+                        }
+                        (Some(_), _) => common::clap_panic("--stdin with --rules takes no args"),
+                        (None, _) => common::clap_panic("--stdin takes the old and the new term"),
+                    }
+                    this.pattern_string = String::new();
+                    this.paths = vec![];
+                }
+                this
+            },
+            |mut this, matches| {
+                let arg_name = "stdout";
+                this.stdout = matches.is_present(arg_name);
+                this
+            },
+            |mut this, matches| {
+                let arg_name = "glob";
+                this.globs = values_of_strings(matches, arg_name);
+                this
+            },
+            |mut this, matches| {
+                let arg_name = "exclude";
+                this.excludes = values_of_strings(matches, arg_name);
+                this
+            },
             |mut this, matches| {
                 let arg_name = "dry-run";
                 this.dry_run = matches.is_present(arg_name);
@@ -110,13 +507,163 @@ impl<'a> ClapArg<'a> for UserInput {
                 this.silent = matches.is_present(arg_name);
                 this
             },
+            |mut this, matches| {
+                let arg_name = "regex";
+                this.regex = matches.is_present(arg_name);
+                this
+            },
+            |mut this, matches| {
+                let arg_name = "ignore-case";
+                this.ignore_case = matches.is_present(arg_name);
+                this
+            },
+            |mut this, matches| {
+                let arg_name = "smart-case";
+                this.smart_case = matches.is_present(arg_name);
+                this
+            },
+            |mut this, matches| {
+                let arg_name = "word";
+                this.word = matches.is_present(arg_name);
+                this
+            },
+            |mut this, matches| {
+                let arg_name = "identifier";
+                this.identifier = matches.value_of(arg_name).map(|val| val.to_string());
+                this
+            },
+            |mut this, matches| {
+                let arg_name = "word-chars";
+                this.word_chars = matches.value_of(arg_name).map(|val| val.to_string());
+                this
+            },
+            |mut this, matches| {
+                let arg_name = "preserve-case";
+                this.preserve_case = matches.is_present(arg_name);
+                this
+            },
+            |mut this, matches| {
+                let arg_name = "multiline";
+                this.multiline = matches.is_present(arg_name);
+                this
+            },
+            |mut this, matches| {
+                let arg_name = "rename-paths";
+                this.rename_paths = matches.is_present(arg_name);
+                this
+            },
+            |mut this, matches| {
+                let arg_name = "interactive";
+                this.interactive = matches.is_present(arg_name);
+                this
+            },
+            |mut this, matches| {
+                let arg_name = "no-ignore";
+                this.no_ignore = matches.is_present(arg_name);
+                this
+            },
+            |mut this, matches| {
+                let arg_name = "threads";
+                this.threads = match matches.value_of(arg_name) {
+                    // zero lets the thread pools pick a count based on the CPUs
+                    None => 0,
+                    Some(val) => val
+                        .parse()
+                        .unwrap_or_else(|error| common::clap_panic(error)),
+                };
+                this
+            },
+            |mut this, matches| {
+                let arg_name = "format";
+                this.format = match matches.value_of(arg_name) {
+                    None if matches.is_present("json") => OutputFormat::Json,
+                    None => OutputFormat::default(),
+                    Some(val) => val
+                        .parse()
+                        .unwrap_or_else(|error| common::clap_panic(error)),
+                };
+                this
+            },
+            |mut this, matches| {
+                let arg_name = "hard-links";
+                this.hard_links = match matches.value_of(arg_name) {
+                    None => HardLinkPolicy::default(),
+                    Some(val) => val
+                        .parse()
+                        .unwrap_or_else(|error| common::clap_panic(error)),
+                };
+                this
+            },
+            |mut this, matches| {
+                let arg_name = "binary";
+                this.binary = matches.is_present(arg_name);
+                this
+            },
+            |mut this, matches| {
+                let arg_name = "after-context";
+                this.after_context = parse_optional_count(matches, arg_name);
+                this
+            },
+            |mut this, matches| {
+                let arg_name = "before-context";
+                this.before_context = parse_optional_count(matches, arg_name);
+                this
+            },
+            |mut this, matches| {
+                let arg_name = "context";
+                this.context = parse_optional_count(matches, arg_name);
+                this
+            },
         ]
     }
 }
 
+#[derive(Default, Debug)]
+pub struct UndoInput {
+    pub run_id: Option<String>,
+}
+
+impl<'a> ClapArg<'a> for UndoInput {
+    fn get_args<'b>() -> Vec<Arg<'a, 'b>> {
+        vec![Arg::with_name("run-id")
+            .help("the id of the run to revert (defaults to the latest run)")
+            .required(false)
+            .takes_value(true)
+            .index(1)]
+    }
+
+    fn from_matches(matches: &ArgMatches) -> ParseResult<Self> {
+        Ok(Self::get_setters()
+            .iter()
+            .fold(Self::default(), |acc, setter| setter(acc, matches)))
+    }
+
+    fn get_setters() -> Vec<fn(Self, &ArgMatches) -> Self> {
+        vec![|mut this, matches| {
+            let arg_name = "run-id";
+            this.run_id = matches.value_of(arg_name).map(|val| val.to_string());
+            this
+        }]
+    }
+}
+
+fn parse_optional_count(matches: &ArgMatches, arg_name: &str) -> Option<usize> {
+    matches.value_of(arg_name).map(|val| {
+        val.parse()
+            .unwrap_or_else(|error| common::clap_panic(error))
+    })
+}
+
+fn values_of_strings(matches: &ArgMatches, arg_name: &str) -> Vec<String> {
+    matches
+        .values_of(arg_name)
+        .map(|vals| vals.map(|val| val.to_string()).collect())
+        .unwrap_or_default()
+}
+
 fn panic_because_of_bad_parse() -> ! {
     clap::Error::with_description(
-        &"Command could not be parsed or was not passed in.",
+        "Command could not be parsed or was not passed in.",
         clap::ErrorKind::ArgumentNotFound,
     )
     .exit()
@@ -125,14 +672,21 @@ fn panic_because_of_bad_parse() -> ! {
 #[cfg(test)]
 mod test {
     use super::*;
-    use clap::{App, AppSettings, ErrorKind, Result as ClapResult};
-    fn get_matches_for_input(input: Vec<&str>) -> ClapResult<ArgMatches> {
+    use clap::{App, AppSettings, ErrorKind, Result as ClapResult, SubCommand};
+    fn get_matches_for_input(input: Vec<&str>) -> ClapResult<ArgMatches<'_>> {
         UserInput::get_args()
             .into_iter()
             .fold(
-                App::new("turbogrep").setting(AppSettings::NoBinaryName),
+                App::new("turbogrep")
+                    .setting(AppSettings::NoBinaryName)
+                    .setting(AppSettings::SubcommandsNegateReqs),
                 |acc, arg| acc.arg(arg),
             )
+            .subcommand(
+                UndoInput::get_args()
+                    .into_iter()
+                    .fold(SubCommand::with_name("undo"), |acc, arg| acc.arg(arg)),
+            )
             .get_matches_from_safe(input)
     }
 
@@ -189,10 +743,285 @@ mod test {
         assert!(user_input.silent);
     }
 
+    #[test]
+    fn optional_regex_flag_should_work() {
+        let mut input = get_required_input_arg_values();
+        input.push("--regex");
+
+        let matches_result = get_matches_for_input(input);
+        assert!(matches_result.is_ok());
+        let user_input = UserInput::from_matches(&matches_result.unwrap()).unwrap();
+        assert!(user_input.regex);
+        assert!(!user_input.dry_run);
+    }
+
+    #[test]
+    fn case_flags_should_set_the_case_sensitivity() {
+        let case_for = |flags: Vec<&str>| {
+            let mut input = get_required_input_arg_values();
+            input.extend(flags);
+            let matches = get_matches_for_input(input).unwrap();
+            UserInput::from_matches(&matches)
+                .unwrap()
+                .match_options()
+                .case
+        };
+
+        assert_eq!(case_for(vec![]), CaseSensitivity::Sensitive);
+        assert_eq!(case_for(vec!["-i"]), CaseSensitivity::Insensitive);
+        assert_eq!(case_for(vec!["--smart-case"]), CaseSensitivity::Smart);
+        // the last of the two flags wins
+        assert_eq!(case_for(vec!["-i", "-S"]), CaseSensitivity::Smart);
+        assert_eq!(case_for(vec!["-S", "-i"]), CaseSensitivity::Insensitive);
+    }
+
+    #[test]
+    fn word_flags_should_set_the_word_chars() {
+        let word_for = |flags: Vec<&str>| {
+            let mut input = get_required_input_arg_values();
+            input.extend(flags);
+            let matches = get_matches_for_input(input).unwrap();
+            UserInput::from_matches(&matches)
+                .unwrap()
+                .match_options()
+                .word
+                .map(|word_chars| word_chars.extra)
+        };
+
+        assert_eq!(word_for(vec![]), None);
+        assert_eq!(word_for(vec!["-w"]), Some("".to_string()));
+        assert_eq!(word_for(vec!["--identifier", "js"]), Some("$".to_string()));
+        assert_eq!(
+            word_for(vec!["--identifier", "css", "--word-chars", "@"]),
+            Some("-@".to_string())
+        );
+    }
+
+    #[test]
+    fn preserve_case_flag_should_conflict_with_regex_flag() {
+        let mut input = get_required_input_arg_values();
+        input.push("--preserve-case");
+
+        let matches_result = get_matches_for_input(input.clone());
+        assert!(matches_result.is_ok());
+        let user_input = UserInput::from_matches(&matches_result.unwrap()).unwrap();
+        assert!(user_input.match_options().preserve_case);
+
+        input.push("--regex");
+        let matches_result = get_matches_for_input(input);
+        assert_eq!(
+            matches_result.err().unwrap().kind,
+            ErrorKind::ArgumentConflict
+        );
+    }
+
+    #[test]
+    fn multiline_flag_should_work() {
+        let mut input = get_required_input_arg_values();
+        input.push("-U");
+
+        let matches = get_matches_for_input(input).unwrap();
+        let user_input = UserInput::from_matches(&matches).unwrap();
+        assert!(user_input.match_options().multiline);
+    }
+
+    #[test]
+    fn rename_paths_flag_should_conflict_with_interactive_flag() {
+        let mut input = get_required_input_arg_values();
+        input.push("--rename-paths");
+        let matches = get_matches_for_input(input.clone()).unwrap();
+        let user_input = UserInput::from_matches(&matches).unwrap();
+        assert!(user_input.rename_paths);
+
+        input.push("--interactive");
+        let matches_result = get_matches_for_input(input);
+        assert_eq!(
+            matches_result.err().unwrap().kind,
+            ErrorKind::ArgumentConflict
+        );
+    }
+
+    #[test]
+    fn interactive_flag_should_conflict_with_dry_run_flag() {
+        let mut input = get_required_input_arg_values();
+        input.push("--interactive");
+        input.push("--dry-run");
+
+        let matches_result = get_matches_for_input(input);
+        assert!(matches_result.is_err());
+        assert_eq!(
+            matches_result.err().unwrap().kind,
+            ErrorKind::ArgumentConflict
+        );
+    }
+
+    #[test]
+    fn paths_should_default_to_the_current_dir() {
+        let matches = get_matches_for_input(get_required_input_arg_values()).unwrap();
+        let user_input = UserInput::from_matches(&matches).unwrap();
+        assert_eq!(user_input.paths, vec!["."]);
+
+        let mut input = get_required_input_arg_values();
+        input.extend(vec!["src", "tests", "Cargo.toml"]);
+        let matches = get_matches_for_input(input).unwrap();
+        let user_input = UserInput::from_matches(&matches).unwrap();
+        assert_eq!(user_input.paths, vec!["src", "tests", "Cargo.toml"]);
+    }
+
+    #[test]
+    fn globs_and_excludes_should_be_repeatable() {
+        let mut input = get_required_input_arg_values();
+        input.extend(vec![
+            "-g",
+            "*.toml",
+            "--glob",
+            "!target/**",
+            "--exclude",
+            "*.lock",
+        ]);
+
+        let matches = get_matches_for_input(input).unwrap();
+        let user_input = UserInput::from_matches(&matches).unwrap();
+        assert_eq!(
+            user_input.path_globs(&user_input.rule_set().unwrap()),
+            vec!["expr", "*.toml", "!target/**", "!*.lock"]
+        );
+    }
+
+    #[test]
+    fn rules_arg_should_turn_the_positional_args_into_paths() {
+        let matches = get_matches_for_input(vec!["--rules", "rules.toml", "src", "tests"]).unwrap();
+        let user_input = UserInput::from_matches(&matches).unwrap();
+        assert_eq!(user_input.rules.as_deref(), Some("rules.toml"));
+        assert_eq!(user_input.paths, vec!["src", "tests"]);
+        assert!(user_input.replaces());
+
+        let matches = get_matches_for_input(vec!["--rules", "rules.toml"]).unwrap();
+        let user_input = UserInput::from_matches(&matches).unwrap();
+        assert_eq!(user_input.paths, vec!["."]);
+
+        let matches_result = get_matches_for_input(vec!["--rules", "rules.toml", "--regex"]);
+        assert_eq!(
+            matches_result.err().unwrap().kind,
+            ErrorKind::ArgumentConflict
+        );
+    }
+
+    #[test]
+    fn stdin_flag_should_take_the_terms_as_positional_args() {
+        let matches = get_matches_for_input(vec!["--stdin", "old", "new"]).unwrap();
+        let user_input = UserInput::from_matches(&matches).unwrap();
+        assert!(user_input.stdin);
+        assert_eq!(user_input.term, "old");
+        assert_eq!(user_input.replacement.as_deref(), Some("new"));
+
+        let matches_result = get_matches_for_input(vec!["--stdin", "--stdout", "old", "new"]);
+        assert_eq!(
+            matches_result.err().unwrap().kind,
+            ErrorKind::ArgumentConflict
+        );
+    }
+
+    #[test]
+    fn stdout_flag_should_work() {
+        let mut input = get_required_input_arg_values();
+        input.push("--stdout");
+
+        let matches = get_matches_for_input(input).unwrap();
+        let user_input = UserInput::from_matches(&matches).unwrap();
+        assert!(user_input.stdout);
+    }
+
+    #[test]
+    fn optional_threads_arg_should_work() {
+        let mut input = get_required_input_arg_values();
+        input.push("--threads");
+        input.push("4");
+
+        let matches_result = get_matches_for_input(input);
+        assert!(matches_result.is_ok());
+        let user_input = UserInput::from_matches(&matches_result.unwrap()).unwrap();
+        assert_eq!(user_input.threads, 4);
+    }
+
+    #[test]
+    fn optional_format_arg_should_work() {
+        let mut input = get_required_input_arg_values();
+        input.push("--format");
+        input.push("diff");
+
+        let matches_result = get_matches_for_input(input);
+        assert!(matches_result.is_ok());
+        let user_input = UserInput::from_matches(&matches_result.unwrap()).unwrap();
+        assert_eq!(user_input.format, OutputFormat::Diff);
+    }
+
+    #[test]
+    fn json_flag_should_set_json_format() {
+        let mut input = get_required_input_arg_values();
+        input.push("--json");
+
+        let matches_result = get_matches_for_input(input);
+        assert!(matches_result.is_ok());
+        let user_input = UserInput::from_matches(&matches_result.unwrap()).unwrap();
+        assert_eq!(user_input.format, OutputFormat::Json);
+    }
+
+    #[test]
+    fn optional_hard_links_arg_should_work() {
+        let mut input = get_required_input_arg_values();
+        input.push("--hard-links");
+        input.push("write-through");
+
+        let matches_result = get_matches_for_input(input);
+        assert!(matches_result.is_ok());
+        let user_input = UserInput::from_matches(&matches_result.unwrap()).unwrap();
+        assert_eq!(user_input.hard_links, HardLinkPolicy::WriteThrough);
+    }
+
+    #[test]
+    fn context_args_should_set_context_lines() {
+        let mut input = get_required_input_arg_values();
+        input.extend(vec!["-C", "0", "-A", "3"]);
+
+        let matches_result = get_matches_for_input(input);
+        assert!(matches_result.is_ok());
+        let user_input = UserInput::from_matches(&matches_result.unwrap()).unwrap();
+        assert_eq!(
+            user_input.context_lines(),
+            ContextLines {
+                before: 0,
+                after: 3
+            }
+        );
+    }
+
+    #[test]
+    fn unknown_format_should_fail_match_parse() {
+        let mut input = get_required_input_arg_values();
+        input.push("--format");
+        input.push("xml");
+
+        let matches_result = get_matches_for_input(input);
+        assert!(matches_result.is_err());
+        assert_eq!(matches_result.err().unwrap().kind, ErrorKind::InvalidValue);
+    }
+
+    #[test]
+    fn undo_subcommand_should_not_require_other_args() {
+        let matches_result = get_matches_for_input(vec!["undo", "some-run"]);
+        assert!(matches_result.is_ok());
+
+        let matches = matches_result.unwrap();
+        let undo_matches = matches.subcommand_matches("undo").unwrap();
+        let undo_input = UndoInput::from_matches(undo_matches).unwrap();
+        assert_eq!(undo_input.run_id, Some("some-run".to_string()));
+    }
+
     #[test]
     fn invalid_input_should_fail_match_parse() {
         let mut input = get_required_input_arg_values();
-        input.push("extra-arg");
+        input.push("--not-a-flag");
 
         let matches_result = get_matches_for_input(input);
         assert!(matches_result.is_err());
@@ -201,4 +1030,20 @@ mod test {
             ErrorKind::UnknownArgument
         );
     }
+
+    #[test]
+    fn new_term_should_be_enough_to_replace() {
+        let matches_result = get_matches_for_input(get_required_input_arg_values());
+        let user_input = UserInput::from_matches(&matches_result.unwrap()).unwrap();
+        assert!(user_input.replaces());
+
+        // the deprecated flag is still accepted, and changes nothing
+        for flag in &["--replace", "-r"] {
+            let mut input = get_required_input_arg_values();
+            input.push(flag);
+            let user_input =
+                UserInput::from_matches(&get_matches_for_input(input).unwrap()).unwrap();
+            assert!(user_input.replaces());
+        }
+    }
 }
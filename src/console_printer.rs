@@ -1,18 +1,212 @@
-use super::file_changes::FileChanges;
-use std::path::Path;
+use super::commands::OutputFormat;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use turbogrep::file_changes::{ContextLines, FileChanges, Hunk, Matcher};
+use turbogrep::file_io::FileData;
+use turbogrep::journal::UndoneRun;
+use turbogrep::path_renames::{self, PathRename};
+use turbogrep::streaming::LongLines;
+use turbogrep::{json_output, preview, streaming, unified_diff};
 
 pub fn print_file_path_header_to_console(file_path: &Path) {
     let separator = "-".repeat(80);
     println!("\nFile: \"{:?}\"\n{}", &file_path, separator);
 }
 
+pub fn print_file_warning<E: fmt::Display>(file_path: &Path, error: &E) {
+    eprintln!("warning: skipped {:?}: {}", file_path, error);
+}
+
+pub fn print_long_lines_warning(file_path: &Path, long_lines: &LongLines) {
+    eprintln!("warning: {:?}: {}", file_path, long_lines);
+}
+
 pub fn print_changes_to_be_made(changes_to_be_made: &FileChanges) {
-    println!("{}", changes_to_be_made);
+    println!("{}", preview::render_changes(changes_to_be_made));
+}
+
+/// Prints the text as it is, failing instead of panicking when stdout is a
+/// closed pipe.
+pub fn print_replaced_text(text: &str) -> io::Result<()> {
+    io::stdout().lock().write_all(text.as_bytes())
+}
+
+/// Prints a hunk of a file too large to hold in memory as soon as it is
+/// found, so that all of them together look like `print_changes_to_be_made`.
+pub fn print_streamed_hunk(hunk: &Hunk, is_first: bool) {
+    if !is_first {
+        println!("--");
+    }
+    println!("{}", preview::render_hunk(hunk));
+}
+
+pub fn print_end_of_streamed_hunks() {
+    println!();
+}
+
+/// Same as `print_replaced_text`, replacing the lines as they are read.
+pub fn print_replaced_lines<R: BufRead>(
+    reader: R,
+    changes_requested: &dyn Matcher,
+) -> io::Result<()> {
+    let mut stdout = io::BufWriter::new(io::stdout().lock());
+    let long_lines = streaming::replace_lines(reader, changes_requested, &mut stdout)?;
+    if let Some(long_lines) = long_lines {
+        print_long_lines_warning(Path::new("-"), &long_lines);
+    }
+    stdout.flush()
+}
+
+/// Same as `print_replaced_text`, for a file too large to hold in memory.
+pub fn print_replaced_file(
+    file_path: &Path,
+    changes_requested: &dyn Matcher,
+    search_binary: bool,
+) -> io::Result<()> {
+    let mut stdout = io::BufWriter::new(io::stdout().lock());
+    let long_lines =
+        streaming::write_replaced_file(file_path, changes_requested, search_binary, &mut stdout)?;
+    if let Some(long_lines) = long_lines {
+        print_long_lines_warning(file_path, &long_lines);
+    }
+    stdout.flush()
 }
 
-pub fn print_current_counters(files_seen: &u32, files_changed: &u32) {
+/// Prints the diff of the file, which also moves it if `renamed_path` differs
+/// from its path.
+pub fn print_unified_diff(
+    file_data: &FileData,
+    changes_to_be_made: &FileChanges,
+    renamed_path: &Path,
+    starting_paths: &[&Path],
+) {
+    match renamed_path == file_data.file_path {
+        true => print!(
+            "{}",
+            unified_diff::unified_diff(file_data, changes_to_be_made, starting_paths)
+        ),
+        false => print!(
+            "{}",
+            unified_diff::moved_file_diff(
+                file_data,
+                changes_to_be_made,
+                renamed_path,
+                starting_paths
+            )
+        ),
+    }
+}
+
+pub fn print_json_file_record(
+    file_data: &FileData,
+    changes_requested: &dyn Matcher,
+    context: ContextLines,
+) {
     println!(
-        "files seen: {}, files changed: {}...",
-        files_seen, files_changed
+        "{}",
+        json_output::file_record(file_data, changes_requested, context)
+    );
+}
+
+/// Prints the renames after the changes to the contents. The diff has no
+/// directories, so it moves every file that ends up at another path instead,
+/// apart from the files already moved by the diff of their contents, which
+/// are not part of `undiffed_paths`.
+pub fn print_renames(
+    renames: &[PathRename],
+    undiffed_paths: &[PathBuf],
+    starting_paths: &[&Path],
+    format: &OutputFormat,
+) {
+    match format {
+        OutputFormat::Preview => {
+            println!("\nRenames:\n{}", "-".repeat(80));
+            renames
+                .iter()
+                .for_each(|rename| println!("{}", preview::render_rename(rename)));
+        }
+        OutputFormat::Diff => undiffed_paths.iter().for_each(|file_path| {
+            let renamed_path = path_renames::renamed_path(file_path, renames);
+            if renamed_path != *file_path {
+                print!(
+                    "{}",
+                    unified_diff::rename_diff(file_path, &renamed_path, starting_paths)
+                );
+            }
+        }),
+        OutputFormat::Json => renames
+            .iter()
+            .for_each(|rename| println!("{}", json_output::rename_record(rename))),
+    }
+}
+
+/// Prints the line to stdout, unless the output format is meant to be piped
+/// into other tools, in which case it goes to stderr instead.
+fn print_status_line(line: String, format: &OutputFormat) {
+    match format {
+        OutputFormat::Preview => println!("{}", line),
+        OutputFormat::Diff | OutputFormat::Json => eprintln!("{}", line),
+    }
+}
+
+pub fn print_summary(
+    files_seen: &usize,
+    files_changed: &usize,
+    run_id: Option<&str>,
+    format: &OutputFormat,
+) {
+    if *format == OutputFormat::Json {
+        println!(
+            "{}",
+            json_output::summary_record(files_seen, files_changed, run_id)
+        );
+        return;
+    }
+
+    print_status_line(
+        format!(
+            "files seen: {}, files changed: {}...",
+            files_seen, files_changed
+        ),
+        format,
     );
+    if let Some(run_id) = run_id {
+        print_status_line(
+            format!(
+                "run id: {} (use `turbogrep undo {}` to revert the changes)",
+                run_id, run_id
+            ),
+            format,
+        );
+    }
+}
+
+pub fn print_renamed_count(paths_renamed: &usize, format: &OutputFormat) {
+    print_status_line(format!("paths renamed: {}", paths_renamed), format);
+}
+
+pub fn print_undone_run(undone_run: &UndoneRun) {
+    undone_run
+        .renames
+        .iter()
+        .rev()
+        .for_each(|rename| println!("moved back: {:?} -> {:?}", rename.to, rename.from));
+    undone_run
+        .restored_paths
+        .iter()
+        .for_each(|path| println!("restored: {:?}", path));
+    match undone_run.renames.is_empty() {
+        true => println!(
+            "undid run {}, files restored: {}",
+            undone_run.run_id,
+            undone_run.restored_paths.len()
+        ),
+        false => println!(
+            "undid run {}, files restored: {}, paths moved back: {}",
+            undone_run.run_id,
+            undone_run.restored_paths.len(),
+            undone_run.renames.len()
+        ),
+    }
 }
@@ -1,45 +1,227 @@
-use super::common;
-use glob;
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::{WalkBuilder, WalkState};
+use std::collections::HashSet;
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
+use std::sync::mpsc;
 
+/// Name of the project-specific ignore file, read with the same syntax and
+/// precedence rules as a `.gitignore`.
+pub const TURBOGREP_IGNORE_FILENAME: &str = ".turbogrepignore";
+
+pub struct WalkerOptions {
+    /// Whether `.gitignore`, `.ignore` and `.turbogrepignore` files (as well
+    /// as the `.git` directory itself) are used to skip paths.
+    pub respect_ignore_files: bool,
+    /// Number of threads used to walk the directories, `0` picks one
+    /// based on the number of available CPUs.
+    pub threads: usize,
+}
+
+impl Default for WalkerOptions {
+    fn default() -> Self {
+        Self {
+            respect_ignore_files: true,
+            threads: 0,
+        }
+    }
+}
+
+/// Globs compiled into a single set, with gitignore-style semantics: a glob
+/// without a `/` (other than a trailing one) matches the name of a file or
+/// directory at any depth, otherwise it is anchored to the starting path. A
+/// glob with a trailing `/` only matches directories, and every file beneath
+/// a matched directory is matched too.
+struct GlobList {
+    files: GlobSet,
+    dirs: GlobSet,
+}
+
+impl GlobList {
+    fn new(exprs: &[&str]) -> io::Result<Self> {
+        let mut files = GlobSetBuilder::new();
+        let mut dirs = GlobSetBuilder::new();
+        for expr in exprs {
+            let dir_only = expr.ends_with('/');
+            let expr = expr.trim_end_matches('/');
+            let expr = match expr.contains('/') {
+                true => expr.trim_start_matches('/').to_string(),
+                false => format!("**/{}", expr),
+            };
+
+            if !dir_only {
+                files.add(compile_glob(&expr)?);
+            }
+            files.add(compile_glob(&format!("{}/**", expr))?);
+            dirs.add(compile_glob(&expr)?);
+            // `generated/**` matches everything inside the directory, so the
+            // directory itself does not need to be walked
+            if let Some(dir_expr) = expr.strip_suffix("/**") {
+                dirs.add(compile_glob(dir_expr)?);
+            }
+        }
+        Ok(Self {
+            files: files.build().map_err(invalid_glob)?,
+            dirs: dirs.build().map_err(invalid_glob)?,
+        })
+    }
+}
+
+/// The globs a path has to match, and the `!`-prefixed ones it must not.
+struct PathGlobs {
+    include: GlobList,
+    exclude: GlobList,
+}
+
+impl PathGlobs {
+    fn new(exprs: &[&str]) -> io::Result<Self> {
+        let (excludes, includes): (Vec<&str>, Vec<&str>) =
+            exprs.iter().partition(|expr| expr.starts_with('!'));
+        let excludes = excludes
+            .iter()
+            .map(|expr| &expr[1..])
+            .collect::<Vec<&str>>();
+        Ok(Self {
+            include: GlobList::new(&includes)?,
+            exclude: GlobList::new(&excludes)?,
+        })
+    }
+
+    /// A path (relative to its starting path) is included if it matches any
+    /// include glob, or there are none, and no exclude glob.
+    fn includes_file(&self, relative_path: &Path) -> bool {
+        let included = self.include.files.is_empty() || self.include.files.is_match(relative_path);
+        included && !self.exclude.files.is_match(relative_path)
+    }
+
+    fn excludes_dir(&self, relative_path: &Path) -> bool {
+        self.exclude.dirs.is_match(relative_path)
+    }
+}
+
+/// Matches files found by the walk against other globs, with the same rules,
+/// e.g. to pick the files that only some of the changes apply to.
+pub struct PathFilter {
+    globs: PathGlobs,
+}
+
+impl PathFilter {
+    pub fn new(exprs: &[&str]) -> io::Result<Self> {
+        Ok(Self {
+            globs: PathGlobs::new(exprs)?,
+        })
+    }
+
+    /// Whether the file, relative to the starting path it was found under,
+    /// matches the globs. A file given as a starting path is matched by its
+    /// name.
+    pub fn matches(&self, file_path: &Path, starting_paths: &[&Path]) -> bool {
+        let relative_path = relative_to_starting_path(file_path, starting_paths);
+        let relative_path = match relative_path.as_os_str().is_empty() {
+            true => file_path.file_name().map_or(file_path, Path::new),
+            false => relative_path,
+        };
+        self.globs.includes_file(relative_path)
+    }
+}
+
+fn compile_glob(expr: &str) -> io::Result<Glob> {
+    GlobBuilder::new(expr)
+        .literal_separator(true)
+        .build()
+        .map_err(invalid_glob)
+}
+
+fn invalid_glob(error: globset::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, error)
+}
+
+/// The path relative to the (innermost) starting path it was found under.
+pub(crate) fn relative_to_starting_path<'a>(path: &'a Path, starting_paths: &[&Path]) -> &'a Path {
+    starting_paths
+        .iter()
+        .filter_map(|starting_path| path.strip_prefix(starting_path).ok())
+        .min_by_key(|relative_path| relative_path.components().count())
+        .unwrap_or(path)
+}
+
+/// Walks every starting path, which can be a directory or a file, for the
+/// files matching any of the globs in `exprs` and none of the `!`-prefixed
+/// ones. Directories matched by a `!`-prefixed glob are not walked at all.
+///
+/// The globs are matched against the paths relative to the starting path
+/// they were found under, see `GlobList` for the rules. They support `*`
+/// (which does not cross directories), `**`, `?`, `{a,b}` and `[a-z]`.
+///
+/// Files given as starting paths are always returned, even if they do not
+/// match the globs. A file reachable from several starting paths (e.g. `.`
+/// and `src`) is only returned once.
 pub fn get_file_paths_that_match_expr(
-    expr: &str,
-    starting_path: &Path,
+    exprs: &[&str],
+    starting_paths: &[&Path],
+    options: &WalkerOptions,
 ) -> io::Result<Vec<PathBuf>> {
-    let matches_pattern = |path: &Path| -> bool {
-        let pattern = match glob::Pattern::new(expr) {
-            Ok(re) => re,
-            Err(error) => common::clap_panic(error),
-        };
-        pattern.matches_path(path)
+    let globs = PathGlobs::new(exprs)?;
+    let (first_path, other_paths) = match starting_paths.split_first() {
+        Some(paths) => paths,
+        None => return Ok(vec![]),
     };
 
-    let mut valid_paths = vec![];
-    let mut add_file_to_list = |file_path: &fs::DirEntry| {
-        if matches_pattern(&file_path.path()) {
-            let full_path = file_path.path();
-            valid_paths.push(full_path);
-        }
-    };
+    let mut walker = WalkBuilder::new(first_path);
+    other_paths.iter().for_each(|path| {
+        walker.add(path);
+    });
+    walker
+        .standard_filters(options.respect_ignore_files)
+        // hidden files were always visited, only the ignore files should filter them
+        .hidden(false)
+        .threads(options.threads);
+    if options.respect_ignore_files {
+        walker
+            .require_git(false)
+            .add_custom_ignore_filename(TURBOGREP_IGNORE_FILENAME)
+            .filter_entry(|entry| entry.file_name() != ".git");
+    }
 
-    fn visit_dirs(dir: &Path, cb: &mut dyn FnMut(&fs::DirEntry)) -> io::Result<()> {
-        if dir.is_dir() {
-            for entry in fs::read_dir(dir)? {
-                let entry = entry?;
-                let path = entry.path();
-                if path.is_dir() {
-                    visit_dirs(&path, cb)?;
-                } else {
-                    cb(&entry);
+    let (sender, receiver) = mpsc::channel();
+    walker.build_parallel().run(|| {
+        let sender = sender.clone();
+        let globs = &globs;
+        Box::new(move |entry| {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(error) => {
+                    sender.send(Err(io::Error::other(error))).unwrap();
+                    return WalkState::Quit;
+                }
+            };
+            let path = entry.path();
+            let is_starting_path = entry.depth() == 0;
+            let relative_path = relative_to_starting_path(path, starting_paths);
+            if path.is_dir() {
+                if !is_starting_path && globs.excludes_dir(relative_path) {
+                    return WalkState::Skip;
                 }
+            } else if is_starting_path || globs.includes_file(relative_path) {
+                sender.send(Ok(path.to_path_buf())).unwrap();
             }
-        }
-        Ok(())
-    }
+            WalkState::Continue
+        })
+    });
+    drop(sender);
 
-    visit_dirs(&starting_path, &mut add_file_to_list)?;
+    // the threads visit the paths in no particular order, so they get sorted
+    // to keep the output deterministic between runs
+    let mut valid_paths = receiver.into_iter().collect::<io::Result<Vec<PathBuf>>>()?;
+    valid_paths.sort_unstable();
+    if !other_paths.is_empty() {
+        let mut seen_paths = HashSet::new();
+        valid_paths.retain(|path| {
+            let canonical_path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+            seen_paths.insert(canonical_path)
+        });
+    }
 
     Ok(valid_paths)
 }
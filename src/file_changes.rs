@@ -1,134 +1,472 @@
-use super::commands::UserInput;
 use super::file_io::FileData;
-use ansi_term::Color;
-use std::cmp::{Ord, Ordering};
-use std::collections::HashSet;
-use std::fmt;
+use super::preserve_case::{self, CaseVariant};
+use regex::{Captures, Regex, RegexBuilder};
+
+/// Number of lines shown before and after every line containing the term.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ContextLines {
+    pub before: usize,
+    pub after: usize,
+}
+
+impl Default for ContextLines {
+    fn default() -> Self {
+        Self {
+            before: 2,
+            after: 2,
+        }
+    }
+}
+
+/// How the case of the letters is taken into account when matching the term.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub enum CaseSensitivity {
+    #[default]
+    Sensitive,
+    /// Letters match regardless of their case, using simple Unicode case
+    /// folding: each letter only matches letters that fold to the same single
+    /// letter, so `ß` matches `ẞ` but not `SS`.
+    Insensitive,
+    /// Insensitive, unless the term contains an uppercase letter.
+    Smart,
+}
+
+/// The characters that make up a word, a match of the term is only kept if it
+/// is not preceded or followed by one of them.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct WordChars {
+    /// Characters that are part of a word on top of the letters, digits and
+    /// `_`, e.g. `$` for identifiers in JavaScript.
+    pub extra: String,
+}
+
+impl WordChars {
+    /// The word characters of identifiers in the given language, `None` for
+    /// an unknown language.
+    pub fn for_language(language: &str) -> Option<Self> {
+        let extra = match language {
+            "c" | "cpp" | "go" | "java" | "python" | "rust" => "",
+            "js" | "javascript" | "ts" | "typescript" => "$",
+            "php" | "perl" | "shell" => "$",
+            "css" | "scss" | "less" | "html" => "-",
+            "lisp" | "clojure" | "elisp" | "scheme" => "-!?*<>=/+",
+            "ruby" => "?!",
+            _ => return None,
+        };
+        Some(Self {
+            extra: extra.to_string(),
+        })
+    }
+
+    pub fn is_word_char(&self, c: char) -> bool {
+        c.is_alphanumeric() || c == '_' || self.extra.contains(c)
+    }
+}
+
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct MatchOptions {
+    /// Whether the term is a regular expression and the replacement may
+    /// reference its capture groups (`$1`, `${name}`), instead of both being
+    /// literal terms.
+    pub regex: bool,
+    pub case: CaseSensitivity,
+    /// Only matches surrounded by word boundaries are kept if set.
+    pub word: Option<WordChars>,
+    /// Whether every case variant of the term (`foo_bar`, `FooBar`, `FOO_BAR`,
+    /// ...) is matched and replaced by the same variant of the replacement.
+    /// The term and replacement are then literal, `regex` and `case` are
+    /// ignored.
+    pub preserve_case: bool,
+    /// Whether the term is matched against the whole text of the file instead
+    /// of each line on its own, so that both the term and the replacement can
+    /// span several lines. `^` and `$` still match at the start and end of
+    /// every line, and the final newline of the file can be matched (to remove
+    /// the last line along with it, say).
+    pub multiline: bool,
+}
+
+/// Finds the matches in the text of a file, either of a single term or of a
+/// whole set of rules.
+pub trait Matcher {
+    /// Whether matches can span several lines, the lines of a file are then
+    /// matched joined with `\n` instead of one by one.
+    fn multiline(&self) -> bool;
+    fn is_match(&self, line: &str) -> bool;
+    /// Every match in the text, which starts at line `first_line` of the
+    /// file, sorted and without overlaps.
+    fn match_spans(&self, text: &str, first_line: usize) -> Vec<MatchSpan>;
+}
 
 pub struct WantedChanges {
-    pub old: String,
     pub new: String,
+    pub options: MatchOptions,
+    pub pattern: Regex,
+    /// The spellings of the term matched in `preserve_case` mode.
+    case_variants: Vec<(String, CaseVariant)>,
+    new_words: Vec<String>,
 }
 
 impl WantedChanges {
-    pub fn from_user_input(user_input: &UserInput) -> Self {
-        Self {
-            old: user_input.term.to_string(),
-            new: user_input.replacement.to_string(),
+    /// Fails if `options.regex` is set and `old` is not a valid regular
+    /// expression.
+    pub fn new(old: String, new: String, options: MatchOptions) -> Result<Self, regex::Error> {
+        if options.preserve_case {
+            let case_variants = preserve_case::variants_of(&preserve_case::split_words(&old));
+            let mut spellings = case_variants
+                .iter()
+                .map(|(spelling, _)| regex::escape(spelling))
+                .collect::<Vec<String>>();
+            // the alternation picks the first spelling that matches
+            spellings.sort_by_key(|spelling| std::cmp::Reverse(spelling.len()));
+            return Ok(Self {
+                pattern: Regex::new(&spellings.join("|"))?,
+                new_words: preserve_case::split_words(&new),
+                new,
+                options,
+                case_variants,
+            });
+        }
+
+        let case_insensitive = match options.case {
+            CaseSensitivity::Sensitive => false,
+            CaseSensitivity::Insensitive => true,
+            CaseSensitivity::Smart => !has_uppercase_letter(&old, options.regex),
+        };
+        let pattern = match options.regex {
+            true => RegexBuilder::new(&old),
+            false => RegexBuilder::new(&regex::escape(&old)),
+        }
+        .case_insensitive(case_insensitive)
+        .multi_line(options.multiline)
+        .build()?;
+        Ok(Self {
+            new,
+            options,
+            pattern,
+            case_variants: vec![],
+            new_words: vec![],
+        })
+    }
+
+    pub fn is_match(&self, line: &str) -> bool {
+        match self.options.word {
+            None => self.pattern.is_match(line),
+            Some(_) => !self.captures(line).is_empty(),
+        }
+    }
+
+    /// Every match of the term in the line, in order, leaving out the ones
+    /// that are not at word boundaries if `options.word` is set.
+    pub fn captures<'t>(&self, line: &'t str) -> Vec<Captures<'t>> {
+        let word_chars = match &self.options.word {
+            None => return self.pattern.captures_iter(line).collect(),
+            Some(word_chars) => word_chars,
+        };
+        let is_word_char = |c: Option<char>| c.is_some_and(|c| word_chars.is_word_char(c));
+
+        let mut captures = vec![];
+        let mut start = 0;
+        while let Some(caps) = self.pattern.captures_at(line, start) {
+            let matched = caps.get(0).unwrap();
+            let before = line[..matched.start()].chars().next_back();
+            let after = line[matched.end()..].chars().next();
+            let at_boundaries =
+                !matched.is_empty() && !is_word_char(before) && !is_word_char(after);
+            // a rejected match may hide a valid one starting inside of it
+            start = match at_boundaries {
+                true => matched.end(),
+                false => match line[matched.start()..].chars().next() {
+                    Some(c) => matched.start() + c.len_utf8(),
+                    None => break,
+                },
+            };
+            if at_boundaries {
+                captures.push(caps);
+            }
+        }
+        captures
+    }
+
+    pub fn replace_all(&self, line: &str) -> String {
+        self.replace_matches(line, |caps| self.expand_replacement(caps))
+    }
+
+    /// Replaces every match of the term in the line with the result of
+    /// `replacement`.
+    pub fn replace_matches<F>(&self, line: &str, replacement: F) -> String
+    where
+        F: Fn(&Captures) -> String,
+    {
+        let mut replaced = String::with_capacity(line.len());
+        let mut last_end = 0;
+        self.captures(line).iter().for_each(|caps| {
+            let matched = caps.get(0).unwrap();
+            replaced.push_str(&line[last_end..matched.start()]);
+            replaced.push_str(&replacement(caps));
+            last_end = matched.end();
+        });
+        replaced.push_str(&line[last_end..]);
+        replaced
+    }
+
+    /// The case variant of the term the match is spelled in, in
+    /// `preserve_case` mode.
+    pub fn case_variant(&self, caps: &Captures) -> Option<CaseVariant> {
+        self.case_variants
+            .iter()
+            .find(|(spelling, _)| *spelling == caps[0])
+            .map(|(_, variant)| *variant)
+    }
+
+    /// Every match of the term in the text, which starts at line `first_line`
+    /// of the file.
+    pub fn match_spans(&self, text: &str, first_line: usize) -> Vec<MatchSpan> {
+        self.captures(text)
+            .iter()
+            .map(|caps| {
+                let matched = caps.get(0).unwrap();
+                let before_match = &text[..matched.start()];
+                let line_start = before_match.rfind('\n').map_or(0, |offset| offset + 1);
+                MatchSpan {
+                    start: matched.start(),
+                    len: matched.len(),
+                    line: first_line + before_match.matches('\n').count(),
+                    column: before_match[line_start..].chars().count(),
+                    replacement: self.expand_replacement(caps),
+                    case_variant: self.case_variant(caps),
+                    rule: None,
+                }
+            })
+            .collect()
+    }
+
+    pub fn expand_replacement(&self, caps: &Captures) -> String {
+        if let Some(variant) = self.case_variant(caps) {
+            return variant.apply(&self.new_words);
+        }
+        match self.options.regex {
+            true => {
+                let mut replacement = String::new();
+                caps.expand(&self.new, &mut replacement);
+                replacement
+            }
+            false => self.new.to_string(),
         }
     }
 }
 
-#[derive(Debug, Eq, PartialEq, Hash, PartialOrd)]
+impl Matcher for WantedChanges {
+    fn multiline(&self) -> bool {
+        self.options.multiline
+    }
+
+    fn is_match(&self, line: &str) -> bool {
+        WantedChanges::is_match(self, line)
+    }
+
+    fn match_spans(&self, text: &str, first_line: usize) -> Vec<MatchSpan> {
+        WantedChanges::match_spans(self, text, first_line)
+    }
+}
+
+/// One match of the term, positioned in the text of its `ParsedLine`.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct MatchSpan {
+    /// Byte offset of the match in the text of the parsed line.
+    pub start: usize,
+    /// Length of the match in bytes.
+    pub len: usize,
+    /// Number of the line of the file the match starts on.
+    pub line: usize,
+    /// Column the match starts at in its line, in characters from 0.
+    pub column: usize,
+    pub replacement: String,
+    /// The case variant of the term the match is spelled in, in
+    /// `preserve_case` mode.
+    pub case_variant: Option<CaseVariant>,
+    /// The label of the rule the match was found by, when matching a set of
+    /// rules.
+    pub rule: Option<String>,
+}
+
+impl MatchSpan {
+    pub fn end(&self) -> usize {
+        self.start + self.len
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Hash)]
 pub struct ParsedLine {
     pub num: usize,
-    pub has_term: bool,
-    pub contents: ChangeContents,
-}
-
-#[derive(Debug, Eq, PartialEq, Hash, PartialOrd)]
-pub struct ChangeContents {
-    pub old: String,
-    pub new: Option<(String, String)>,
-}
-
-impl ChangeContents {
-    pub fn from_line(line: &str, changes_requested: &WantedChanges, has_term: bool) -> Self {
-        let old = &changes_requested.old;
-        let new = &changes_requested.new;
-        match has_term {
-            true => Self {
-                old: replace_terms_and_highlight(line, old, old, Color::Red),
-                new: Some((
-                    replace_terms_and_highlight(line, old, new, Color::Green),
-                    line.replace(old, new),
-                )),
-            },
-            false => Self {
-                old: line.to_string(),
-                new: None,
-            },
+    /// The number of lines of the file this stands for, more than one when a
+    /// multi-line match spans them (the text then holds all of them, joined
+    /// with `\n`).
+    pub line_count: usize,
+    /// The text of the line as it is in the file, without its line ending,
+    /// except for the final newline of the file, which the text of a
+    /// multi-line match on the last line ends with.
+    pub text: String,
+    /// Every match of the term in the text, none for a context line.
+    pub spans: Vec<MatchSpan>,
+}
+
+impl ParsedLine {
+    pub fn has_term(&self) -> bool {
+        !self.spans.is_empty()
+    }
+
+    /// The text with the replacement of every span in place of the match.
+    pub fn new_text(&self) -> String {
+        let mut new_text = String::with_capacity(self.text.len());
+        let mut last_end = 0;
+        self.spans.iter().for_each(|span| {
+            new_text.push_str(&self.text[last_end..span.start]);
+            new_text.push_str(&span.replacement);
+            last_end = span.end();
+        });
+        new_text.push_str(&self.text[last_end..]);
+        new_text
+    }
+
+    /// The lines the new text is made of, and whether the last of them keeps
+    /// the line ending of the last line replaced. It does not when the match
+    /// took the final newline of the file and the replacement has none, which
+    /// leaves no line at all for an empty replacement.
+    pub fn new_lines(&self) -> (Vec<String>, bool) {
+        let new_text = self.new_text();
+        if !self.text.ends_with('\n') {
+            return (split_lines(&new_text), true);
         }
+        match new_text.strip_suffix('\n') {
+            Some(new_text) => (split_lines(new_text), true),
+            None if new_text.is_empty() => (vec![], false),
+            None => (split_lines(&new_text), false),
+        }
+    }
+
+    /// Replaces the whole text of the line, e.g. with a version edited by
+    /// hand, by a single span. The final newline of the file, if the text has
+    /// it, is kept.
+    pub fn set_new_text(&mut self, mut new_text: String) {
+        if self.text.ends_with('\n') {
+            new_text.push('\n');
+        }
+        self.spans = vec![MatchSpan {
+            start: 0,
+            len: self.text.len(),
+            line: self.num,
+            column: 0,
+            replacement: new_text,
+            case_variant: None,
+            rule: None,
+        }];
     }
 }
 
-impl fmt::Display for ChangeContents {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let mut display_string = format!("{}", &self.old);
-        if let Some(new) = &self.new {
-            display_string.push_str(&format!(" -> {}", new.0))
+fn split_lines(text: &str) -> Vec<String> {
+    text.split('\n').map(str::to_string).collect()
+}
+
+/// Whether the term has an uppercase letter, ignoring the escape sequences of
+/// a regular expression such as `\W` or `\S`.
+fn has_uppercase_letter(term: &str, regex: bool) -> bool {
+    let mut chars = term.chars();
+    while let Some(c) = chars.next() {
+        if regex && c == '\\' {
+            chars.next();
+        } else if c.is_uppercase() {
+            return true;
         }
-        write!(f, "{}", display_string)
     }
+    false
 }
 
-fn replace_terms_and_highlight(line: &str, old: &str, new: &str, highlight_color: Color) -> String {
-    let colored_string = highlight_color.paint(new).to_string();
-    line.replace(old, &colored_string)
+/// A range of consecutive lines of a file, made of the lines containing the
+/// term and the context lines around them.
+#[derive(Debug, Eq, PartialEq, Hash)]
+pub struct Hunk {
+    pub lines: Vec<ParsedLine>,
 }
 
-impl Ord for ParsedLine {
-    fn cmp(&self, other: &Self) -> Ordering {
-        self.num.cmp(&other.num)
+impl Hunk {
+    /// The number of the first line in the hunk.
+    pub fn start(&self) -> usize {
+        self.lines[0].num
+    }
+
+    pub fn changed_lines(&self) -> impl Iterator<Item = &ParsedLine> {
+        self.lines.iter().filter(|line| line.has_term())
     }
 }
 
 #[derive(Debug, Eq, PartialEq, Hash)]
 pub struct FileChanges {
-    pub lines: Vec<ParsedLine>,
+    pub hunks: Vec<Hunk>,
 }
 
 impl FileChanges {
-    pub fn from_file_data(file_data: &FileData, changes_requested: &WantedChanges) -> Self {
-        let old_term = &changes_requested.old;
-        let mut line_set = HashSet::new();
-        file_data.term_containing_lines.iter().for_each(|line_num| {
-            let half_offset: usize = 2;
-            let full_offset = (half_offset * 2) + 1;
-            let start_index = line_num.checked_sub(half_offset).unwrap_or(0);
-            // we only want to take a few lines surrounding the painted one
-            let mut line_num = start_index;
-            file_data
-                .contents
-                .iter()
-                .skip(start_index)
-                .take(full_offset)
-                .for_each(|line| {
-                    let has_term = line.contains(old_term);
-                    let contents = ChangeContents::from_line(&line, changes_requested, has_term);
-                    let num = line_num;
-                    line_num += 1;
-                    let parsed_line = ParsedLine {
-                        has_term,
-                        num,
-                        contents,
-                    };
-                    line_set.insert(parsed_line);
-                });
+    pub fn from_file_data(
+        file_data: &FileData,
+        changes_requested: &dyn Matcher,
+        context: ContextLines,
+    ) -> Self {
+        let last_line_num = file_data.contents.len().saturating_sub(1);
+        let mut ranges: Vec<(usize, usize)> = vec![];
+        file_data.term_spans.iter().for_each(|(first, last)| {
+            // we only want to take a few lines surrounding the painted ones
+            let start = first.saturating_sub(context.before);
+            let end = (last + context.after).min(last_line_num);
+            match ranges.last_mut() {
+                // ranges that overlap or touch end up in the same hunk
+                Some(range) if start <= range.1 + 1 => range.1 = range.1.max(end),
+                _ => ranges.push((start, end)),
+            }
         });
-        let mut lines = line_set.into_iter().collect::<Vec<ParsedLine>>();
-        lines.sort_unstable();
 
-        Self { lines }
+        // the spans are sorted, so a cursor that moves along with the lines
+        // finds them without searching the whole list for every line
+        let mut term_spans = file_data.term_spans.iter().peekable();
+        let hunks = ranges
+            .into_iter()
+            .map(|(start, end)| {
+                let mut lines = vec![];
+                let mut num = start;
+                while num <= end {
+                    while term_spans.next_if(|(first, _)| *first < num).is_some() {}
+                    let span = term_spans.next_if(|(first, _)| *first == num);
+                    let line_count = match span {
+                        Some((first, last)) => last - first + 1,
+                        None => 1,
+                    };
+                    let (text, spans) = match span {
+                        Some(&(first, last)) => {
+                            let text =
+                                file_data.span_text(first, last, changes_requested.multiline());
+                            let spans = changes_requested.match_spans(&text, num);
+                            (text, spans)
+                        }
+                        None => (file_data.contents[num].clone(), vec![]),
+                    };
+                    lines.push(ParsedLine {
+                        num,
+                        line_count,
+                        text,
+                        spans,
+                    });
+                    num += line_count;
+                }
+                Hunk { lines }
+            })
+            .collect();
+
+        Self { hunks }
     }
-}
 
-impl fmt::Display for ParsedLine {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:>3}|  {}", self.num, self.contents)
+    pub fn lines(&self) -> impl Iterator<Item = &ParsedLine> {
+        self.hunks.iter().flat_map(|hunk| hunk.lines.iter())
     }
-}
 
-impl fmt::Display for FileChanges {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "{}\n",
-            self.lines
-                .iter()
-                .map(|line| line.to_string())
-                .collect::<Vec<String>>()
-                .join("\n")
-        )
+    pub fn is_empty(&self) -> bool {
+        self.hunks.is_empty()
     }
 }
@@ -1,56 +1,501 @@
-use super::commands::UserInput;
-use super::file_changes::{self, FileChanges};
-use super::{console_printer, dir_walker};
-use std::io;
+use super::file_changes::{ContextLines, FileChanges, Matcher};
+use super::journal::Journal;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufWriter, Write};
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-pub fn execute(user_input: UserInput) -> io::Result<()> {
-    let init_path = Path::new(".");
-    let file_paths =
-        dir_walker::get_file_paths_that_match_expr(&user_input.pattern_string, &init_path)?;
+/// How a file that has more than one hard link is written.
+///
+/// Symbolic links are always written through: the file they point to gets the
+/// new contents and the link itself is left untouched.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub enum HardLinkPolicy {
+    /// The path gets replaced atomically by a new file, so the other links
+    /// keep pointing at the original contents.
+    #[default]
+    Replace,
+    /// The file is rewritten in place so that every link sees the new
+    /// contents, at the cost of the write not being atomic.
+    WriteThrough,
+}
+
+impl FromStr for HardLinkPolicy {
+    type Err = String;
+
+    fn from_str(policy: &str) -> Result<Self, Self::Err> {
+        match policy {
+            "replace" => Ok(Self::Replace),
+            "write-through" => Ok(Self::WriteThrough),
+            _ => Err(format!("unknown hard link policy \"{}\"", policy)),
+        }
+    }
+}
+
+pub(crate) const UTF8_BOM: &str = "\u{feff}";
+
+/// The terminator a line had in the original file, kept so that rewriting a
+/// file only changes the bytes that were actually replaced.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+    /// Only possible for the last line, when the file does not end in a newline.
+    None,
+}
+
+impl LineEnding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Lf => "\n",
+            Self::CrLf => "\r\n",
+            Self::None => "",
+        }
+    }
+}
+
+pub struct FileData {
+    pub file_path: PathBuf,
+    /// Whether the file started with a UTF-8 byte order mark, which is not part
+    /// of the first line.
+    pub bom: bool,
+    pub contents: Vec<String>,
+    pub line_endings: Vec<LineEnding>,
+    /// Every line that contains (a part of) a match of the term.
+    pub term_containing_lines: Vec<usize>,
+    /// The first and last line of every group of lines whose matches are
+    /// replaced together, which is one line each unless matching multi-line.
+    pub term_spans: Vec<(usize, usize)>,
+}
+
+impl FileData {
+    /// Splits the text into lines, returning `None` if none of them contains
+    /// the term.
+    pub fn from_text(
+        file_path: &Path,
+        text: &str,
+        changes_requested: &dyn Matcher,
+    ) -> Option<Self> {
+        let bom = text.starts_with(UTF8_BOM);
+        let text = text.strip_prefix(UTF8_BOM).unwrap_or(text);
+        let mut contents = vec![];
+        let mut line_endings = vec![];
+        let mut term_spans = vec![];
+
+        for (line_num, line) in text.split_inclusive('\n').enumerate() {
+            let (line, line_ending) = match line.strip_suffix('\n') {
+                Some(line) => match line.strip_suffix('\r') {
+                    Some(line) => (line, LineEnding::CrLf),
+                    None => (line, LineEnding::Lf),
+                },
+                None => (line, LineEnding::None),
+            };
+            if !changes_requested.multiline() && changes_requested.is_match(line) {
+                term_spans.push((line_num, line_num));
+            }
+            contents.push(line.to_string());
+            line_endings.push(line_ending);
+        }
+        if changes_requested.multiline() {
+            let final_newline = line_endings
+                .last()
+                .is_some_and(|&ending| ending != LineEnding::None);
+            term_spans = multiline_term_spans(&contents, final_newline, changes_requested);
+        }
+        let term_containing_lines = term_spans
+            .iter()
+            .flat_map(|&(first, last)| first..=last)
+            .collect::<Vec<usize>>();
+
+        match term_spans.is_empty() {
+            true => None,
+            false => Some(Self {
+                file_path: file_path.to_path_buf(),
+                bom,
+                term_containing_lines,
+                term_spans,
+                contents,
+                line_endings,
+            }),
+        }
+    }
+
+    /// Whether the last line of the file is missing a trailing newline.
+    pub fn missing_final_newline(&self) -> bool {
+        self.line_endings.last() == Some(&LineEnding::None)
+    }
+
+    /// The text the matches of lines `first..=last` are found in: the lines
+    /// joined with `\n`, and when matching multi-line, followed by the final
+    /// newline of the file if they include the last line and it has one.
+    pub fn span_text(&self, first: usize, last: usize, multiline: bool) -> String {
+        let mut text = self.contents[first..=last].join("\n");
+        if multiline && last + 1 == self.contents.len() && !self.missing_final_newline() {
+            text.push('\n');
+        }
+        text
+    }
+}
+
+/// Matches the term against the lines joined with `\n` (whatever their line
+/// endings were), followed by the final newline of the file if it has one, and
+/// returns the first and last line of every match. Matches that share a line
+/// are merged, as they have to be replaced together.
+fn multiline_term_spans(
+    lines: &[String],
+    final_newline: bool,
+    changes_requested: &dyn Matcher,
+) -> Vec<(usize, usize)> {
+    // an empty file has no line for even an empty match to be on
+    if lines.is_empty() {
+        return vec![];
+    }
+    let mut text = lines.join("\n");
+    if final_newline {
+        text.push('\n');
+    }
+    let mut line_starts = Vec::with_capacity(lines.len());
+    lines.iter().fold(0, |line_start, line| {
+        line_starts.push(line_start);
+        line_start + line.len() + 1
+    });
+    let line_at = |offset: usize| line_starts.partition_point(|&start| start <= offset) - 1;
 
-    let changes_requested = file_changes::WantedChanges::from_user_input(&user_input);
+    let mut spans: Vec<(usize, usize)> = vec![];
+    changes_requested
+        .match_spans(&text, 0)
+        .iter()
+        .for_each(|matched| {
+            let first = line_at(matched.start);
+            let last = match matched.len > 0 {
+                // a match ending with a newline joins the next line to its own
+                true if text[..matched.end()].ends_with('\n') => line_at(matched.end()),
+                true => line_at(matched.end() - 1),
+                false => first,
+            };
+            match spans.last_mut() {
+                Some(span) if first <= span.1 => span.1 = span.1.max(last),
+                _ => spans.push((first, last)),
+            }
+        });
+    spans
+}
+
+/// How many bytes from the start of a file are checked for NUL bytes to
+/// decide whether it is binary, the same heuristic `git` and `grep` use.
+const BINARY_CHECK_LEN: usize = 8000;
 
-    let mut files_seen = 0;
-    let mut files_changed = 0;
+pub fn is_binary(contents: &[u8]) -> bool {
+    contents[..contents.len().min(BINARY_CHECK_LEN)].contains(&0)
+}
 
-    for file_path in file_paths.iter() {
-        files_seen += 1;
-        let possible_data =
-            file_io::read_file_data_and_check_for_match(file_path, &changes_requested.old)?;
-        if let Some(file_data) = possible_data {
-            let changes_to_be_made = FileChanges::from_file_data(&file_data, &changes_requested);
+/// Reads the file and returns its data if any line contains the term.
+///
+/// Binary files are skipped (returning `None`) unless `search_binary` is set.
+/// Files that are not valid UTF-8 cannot be rewritten without mangling them,
+/// so they return an `InvalidData` error for the caller to report.
+pub fn read_file_data_and_check_for_match(
+    file_path: &Path,
+    changes_requested: &dyn Matcher,
+    search_binary: bool,
+) -> io::Result<Option<FileData>> {
+    let text = match read_file_text(file_path, search_binary)? {
+        Some(text) => text,
+        None => return Ok(None),
+    };
+    Ok(FileData::from_text(file_path, &text, changes_requested))
+}
 
-            if !user_input.silent {
-                console_printer::print_file_path_header_to_console(file_path);
-                console_printer::print_changes_to_be_made(&changes_to_be_made);
+/// Reads the whole text of the file, `None` for a binary file unless
+/// `search_binary` is set, with the same errors as
+/// `read_file_data_and_check_for_match`.
+pub fn read_file_text(file_path: &Path, search_binary: bool) -> io::Result<Option<String>> {
+    let contents = fs::read(file_path)?;
+    if !search_binary && is_binary(&contents) {
+        return Ok(None);
+    }
+    text_from_utf8(contents).map(Some)
+}
+
+/// Fails with an `InvalidData` error pointing at the first invalid byte.
+pub fn text_from_utf8(contents: Vec<u8>) -> io::Result<String> {
+    String::from_utf8(contents).map_err(|error| invalid_utf8(error.utf8_error().valid_up_to()))
+}
+
+pub(crate) fn invalid_utf8(offset: usize) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "file is not valid UTF-8 (invalid byte at offset {})",
+            offset
+        ),
+    )
+}
+
+/// The text with every match replaced, the text as it is if nothing matches.
+/// Streams (e.g. standard input) that have no path can use any name for it.
+pub fn replace_in_text(file_path: &Path, text: &str, changes_requested: &dyn Matcher) -> String {
+    match FileData::from_text(file_path, text, changes_requested) {
+        Some(file_data) => {
+            let changes = FileChanges::from_file_data(
+                &file_data,
+                changes_requested,
+                ContextLines {
+                    before: 0,
+                    after: 0,
+                },
+            );
+            apply_changes(&file_data, &changes)
+        }
+        None => text.to_string(),
+    }
+}
+
+/// Returns the new contents of the file, with the changed lines replaced and
+/// everything else (line endings, byte order mark) kept as it was.
+///
+/// Newlines in the replaced lines get the line ending of the first line they
+/// replace, and the last of them keeps the ending of the last line replaced,
+/// unless a multi-line match took the final newline of the file away.
+pub fn apply_changes(file_data: &FileData, changes: &FileChanges) -> String {
+    let mut new_text = match file_data.bom {
+        true => UTF8_BOM.to_string(),
+        false => String::new(),
+    };
+    let mut changed_lines = changes.lines().filter(|line| line.has_term()).peekable();
+    let mut line_num = 0;
+    while line_num < file_data.contents.len() {
+        let changed_line = match changed_lines.next_if(|line| line.num == line_num) {
+            Some(changed_line) => changed_line,
+            None => {
+                new_text.push_str(&file_data.contents[line_num]);
+                new_text.push_str(file_data.line_endings[line_num].as_str());
+                line_num += 1;
+                continue;
+            }
+        };
+
+        let last_num = line_num + changed_line.line_count - 1;
+        let (new_lines, keeps_line_ending) = changed_line.new_lines();
+        let inner_ending = match file_data.line_endings[line_num] {
+            LineEnding::None => LineEnding::Lf,
+            line_ending => line_ending,
+        };
+        new_lines.iter().enumerate().for_each(|(index, new_line)| {
+            new_text.push_str(new_line);
+            match index + 1 == new_lines.len() {
+                true if keeps_line_ending => {
+                    new_text.push_str(file_data.line_endings[last_num].as_str())
+                }
+                true => {}
+                false => new_text.push_str(inner_ending.as_str()),
             }
+        });
+        line_num = last_num + 1;
+    }
+    new_text
+}
 
-            if !user_input.dry_run {
-                files_changed += 1;
-                file_io::execute_changes_to_file(file_data, changes_to_be_made)?;
+/// Writes the changes to the file, saving its original contents to the
+/// journal first if one is given.
+pub fn execute_changes_to_file(
+    file_data: FileData,
+    changes: FileChanges,
+    journal: Option<&Journal>,
+    hard_links: HardLinkPolicy,
+) -> io::Result<()> {
+    let contents = apply_changes(&file_data, &changes);
+    if let Some(journal) = journal {
+        let original = fs::read(&file_data.file_path)?;
+        journal.record(&file_data.file_path, &original, contents.as_bytes())?;
+    }
+    write_file_atomically(&file_data.file_path, contents.as_bytes(), hard_links)
+}
+
+/// Writes the contents to a temporary file next to the target, syncs it and
+/// renames it over the target, so that an interrupted write never leaves a
+/// truncated file behind. The permissions (and ownership, where allowed) of
+/// the original file are kept.
+pub fn write_file_atomically(
+    file_path: &Path,
+    contents: &[u8],
+    hard_links: HardLinkPolicy,
+) -> io::Result<()> {
+    // resolving the path writes through symlinks instead of replacing them
+    let target_path = fs::canonicalize(file_path)?;
+    let metadata = fs::metadata(&target_path)?;
+
+    if hard_links == HardLinkPolicy::WriteThrough && has_other_hard_links(&metadata) {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .open(&target_path)?;
+        file.write_all(contents)?;
+        return file.sync_all();
+    }
+
+    let mut replacement = ReplacementFile::create(file_path, HardLinkPolicy::Replace)?;
+    replacement.write_all(contents)?;
+    replacement.commit()
+}
+
+/// A temporary file next to the file it replaces, for contents too large to
+/// hold in memory: they are written to it bit by bit, and `commit` then puts
+/// it in place of the file with the same guarantees as
+/// `write_file_atomically`. The temporary file is removed if it is dropped
+/// without being committed.
+pub struct ReplacementFile {
+    temp_path: PathBuf,
+    target_path: PathBuf,
+    metadata: fs::Metadata,
+    hard_links: HardLinkPolicy,
+    temp_file: Option<BufWriter<fs::File>>,
+}
+
+impl ReplacementFile {
+    pub fn create(file_path: &Path, hard_links: HardLinkPolicy) -> io::Result<Self> {
+        // resolving the path writes through symlinks instead of replacing them
+        let target_path = fs::canonicalize(file_path)?;
+        let metadata = fs::metadata(&target_path)?;
+        let temp_path = temp_path_next_to(&target_path);
+        let temp_file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)?;
+        Ok(Self {
+            temp_path,
+            target_path,
+            metadata,
+            hard_links,
+            temp_file: Some(BufWriter::new(temp_file)),
+        })
+    }
+
+    /// With `HardLinkPolicy::WriteThrough` and other hard links to the file,
+    /// the contents are copied into the shared file instead of renaming the
+    /// temporary file over it.
+    pub fn commit(mut self) -> io::Result<()> {
+        let temp_file = match self.temp_file.take() {
+            Some(temp_file) => temp_file.into_inner().map_err(|error| error.into_error())?,
+            None => return Ok(()),
+        };
+        let result = (|| {
+            temp_file.set_permissions(self.metadata.permissions())?;
+            copy_ownership(&temp_file, &self.metadata);
+            temp_file.sync_all()?;
+            if self.hard_links == HardLinkPolicy::WriteThrough
+                && has_other_hard_links(&self.metadata)
+            {
+                let mut target_file = OpenOptions::new()
+                    .write(true)
+                    .truncate(true)
+                    .open(&self.target_path)?;
+                io::copy(&mut fs::File::open(&self.temp_path)?, &mut target_file)?;
+                target_file.sync_all()?;
+                return fs::remove_file(&self.temp_path);
             }
+            fs::rename(&self.temp_path, &self.target_path)
+        })();
+        if result.is_err() {
+            let _ = fs::remove_file(&self.temp_path);
+        }
+        result?;
+
+        sync_parent_dir(&self.target_path)
+    }
+}
+
+impl Write for ReplacementFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.temp_file {
+            Some(temp_file) => temp_file.write(buf),
+            None => Err(io::Error::other("replacement file already committed")),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.temp_file {
+            Some(temp_file) => temp_file.flush(),
+            None => Ok(()),
         }
     }
+}
+
+impl Drop for ReplacementFile {
+    fn drop(&mut self) {
+        if self.temp_file.take().is_some() {
+            let _ = fs::remove_file(&self.temp_path);
+        }
+    }
+}
 
-    console_printer::print_current_counters(&files_seen, &files_changed);
+fn temp_path_next_to(target_path: &Path) -> PathBuf {
+    static TEMP_FILE_COUNT: AtomicUsize = AtomicUsize::new(0);
+    let file_name = target_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    target_path.with_file_name(format!(
+        ".{}.turbogrep-{}-{}.tmp",
+        file_name,
+        std::process::id(),
+        TEMP_FILE_COUNT.fetch_add(1, Ordering::Relaxed)
+    ))
+}
 
+#[cfg(unix)]
+fn has_other_hard_links(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    metadata.nlink() > 1
+}
+
+#[cfg(not(unix))]
+fn has_other_hard_links(_metadata: &fs::Metadata) -> bool {
+    false
+}
+
+#[cfg(unix)]
+fn copy_ownership(file: &fs::File, metadata: &fs::Metadata) {
+    use std::os::unix::fs::{fchown, MetadataExt};
+    // only privileged users can give files away, so this is best effort
+    let _ = fchown(file, Some(metadata.uid()), Some(metadata.gid()));
+}
+
+#[cfg(not(unix))]
+fn copy_ownership(_file: &fs::File, _metadata: &fs::Metadata) {}
+
+#[cfg(unix)]
+fn sync_parent_dir(file_path: &Path) -> io::Result<()> {
+    match file_path.parent() {
+        Some(dir) => fs::File::open(dir)?.sync_all(),
+        None => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_parent_dir(_file_path: &Path) -> io::Result<()> {
     Ok(())
 }
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::file_changes::{ContextLines, MatchOptions, WantedChanges};
+    use crate::test_dir::mock_wanted_changes;
+    use crate::{dir_walker, file_changes};
 
     mod dir_file_walker {
         use super::dir_walker::*;
         use super::*;
+        use crate::test_dir::TestDir;
 
         #[test]
         fn should_read_files_from_src_dir() {
-            let expr = &"*.rs";
+            let expr = "*.rs";
             let init_path = Path::new(".");
             let files = unwrap_and_check_ok(
-                get_file_paths_that_match_expr(expr, &init_path),
+                get_file_paths_that_match_expr(&[expr], &[init_path], &WalkerOptions::default()),
                 "result from parsing dirs should not be err",
             );
 
@@ -59,23 +504,203 @@ mod tests {
 
         #[test]
         fn dirs_should_have_correct_pattern_match() {
-            let expr = &"*.rs";
+            let expr = "*.rs";
             let init_path = Path::new(".");
             let files = unwrap_and_check_ok(
-                get_file_paths_that_match_expr(expr, &init_path),
+                get_file_paths_that_match_expr(&[expr], &[init_path], &WalkerOptions::default()),
                 "result from parsing dirs should not be err",
             );
 
             assert!(!files.is_empty());
 
-            let pattern = glob::Pattern::new(expr).unwrap();
+            // a glob without a `/` matches the names of the files
+            let pattern = globset::Glob::new(expr).unwrap().compile_matcher();
             files.into_iter().for_each(|path| {
                 assert!(
-                    pattern.matches_path(&path),
+                    pattern.is_match(path.file_name().unwrap()),
                     "paths of files received should match pattern"
                 );
             });
         }
+
+        #[test]
+        fn paths_should_be_in_the_same_order_for_any_thread_count() {
+            let expr = "*.rs";
+            let init_path = Path::new(".");
+            let single_threaded_files = unwrap_and_check_ok(
+                get_file_paths_that_match_expr(
+                    &[expr],
+                    &[init_path],
+                    &WalkerOptions {
+                        threads: 1,
+                        ..WalkerOptions::default()
+                    },
+                ),
+                "result from parsing dirs should not be err",
+            );
+            let multi_threaded_files = unwrap_and_check_ok(
+                get_file_paths_that_match_expr(
+                    &[expr],
+                    &[init_path],
+                    &WalkerOptions {
+                        threads: 4,
+                        ..WalkerOptions::default()
+                    },
+                ),
+                "result from parsing dirs should not be err",
+            );
+
+            assert!(!single_threaded_files.is_empty());
+            assert_eq!(single_threaded_files, multi_threaded_files);
+        }
+
+        #[test]
+        fn ignored_paths_should_be_skipped_unless_disabled() {
+            let root = TestDir::new("walker-ignore");
+            let nested = root.join("nested");
+            std::fs::create_dir_all(root.join("target")).unwrap();
+            std::fs::create_dir_all(&nested).unwrap();
+            std::fs::write(root.join(".gitignore"), "target/\n").unwrap();
+            std::fs::write(root.join(TURBOGREP_IGNORE_FILENAME), "custom.rs\n").unwrap();
+            std::fs::write(nested.join(".ignore"), "local.rs\n").unwrap();
+            for file in &["kept.rs", "custom.rs", "target/built.rs"] {
+                std::fs::write(root.join(file), "").unwrap();
+            }
+            for file in &["kept.rs", "local.rs"] {
+                std::fs::write(nested.join(file), "").unwrap();
+            }
+
+            let files = unwrap_and_check_ok(
+                get_file_paths_that_match_expr(&["*.rs"], &[&root], &WalkerOptions::default()),
+                "result from parsing dirs should not be err",
+            );
+            let all_files = unwrap_and_check_ok(
+                get_file_paths_that_match_expr(
+                    &["*.rs"],
+                    &[&root],
+                    &WalkerOptions {
+                        respect_ignore_files: false,
+                        ..WalkerOptions::default()
+                    },
+                ),
+                "result from parsing dirs should not be err",
+            );
+
+            assert_eq!(files, vec![root.join("kept.rs"), nested.join("kept.rs")]);
+            assert_eq!(all_files.len(), 5);
+        }
+
+        #[test]
+        fn negated_globs_should_skip_files_and_whole_dirs() {
+            let root = TestDir::new("walker-globs");
+            let generated = root.join("src").join("generated");
+            std::fs::create_dir_all(&generated).unwrap();
+            for file in &[
+                "Cargo.toml",
+                "Cargo.lock",
+                "src/main.rs",
+                "src/generated/api.rs",
+            ] {
+                std::fs::write(root.join(file), "").unwrap();
+            }
+
+            let files = unwrap_and_check_ok(
+                get_file_paths_that_match_expr(
+                    &["*.rs", "*.toml", "!**/generated/**"],
+                    &[&root],
+                    &WalkerOptions::default(),
+                ),
+                "result from parsing dirs should not be err",
+            );
+            let only_excludes = unwrap_and_check_ok(
+                get_file_paths_that_match_expr(
+                    &["!*.lock", "!**/generated"],
+                    &[&root],
+                    &WalkerOptions::default(),
+                ),
+                "result from parsing dirs should not be err",
+            );
+
+            let expected = vec![root.join("Cargo.toml"), root.join("src").join("main.rs")];
+            assert_eq!(files, expected);
+            assert_eq!(only_excludes, expected);
+        }
+
+        #[test]
+        fn globs_should_match_paths_relative_to_the_root() {
+            let root = TestDir::new("walker-anchor");
+            std::fs::create_dir_all(root.join("src/bin")).unwrap();
+            std::fs::create_dir_all(root.join("docs")).unwrap();
+            for file in &[
+                "main.rs",
+                "Cargo.toml",
+                "src/lib.rs",
+                "src/bin/cli.rs",
+                "docs/a.md",
+            ] {
+                std::fs::write(root.join(file), "").unwrap();
+            }
+            let files_for = |exprs: &[&str]| {
+                unwrap_and_check_ok(
+                    get_file_paths_that_match_expr(exprs, &[&root], &WalkerOptions::default()),
+                    "result from parsing dirs should not be err",
+                )
+                .into_iter()
+                .map(|path| path.strip_prefix(&*root).unwrap().to_path_buf())
+                .collect::<Vec<PathBuf>>()
+            };
+
+            // names match at any depth, but `*` does not cross directories
+            let all_rs = vec![
+                PathBuf::from("main.rs"),
+                PathBuf::from("src/bin/cli.rs"),
+                PathBuf::from("src/lib.rs"),
+            ];
+            assert_eq!(files_for(&["*.rs"]), all_rs);
+            assert_eq!(files_for(&["src/*.rs"]), vec![PathBuf::from("src/lib.rs")]);
+            assert_eq!(files_for(&["/*.rs"]), vec![PathBuf::from("main.rs")]);
+            assert_eq!(files_for(&["src/**/*.rs"]), all_rs[1..].to_vec());
+            assert_eq!(
+                files_for(&["*.{toml,md}"]),
+                vec![PathBuf::from("Cargo.toml"), PathBuf::from("docs/a.md")]
+            );
+            assert_eq!(
+                files_for(&["[a-l]*.rs"]),
+                vec![PathBuf::from("src/bin/cli.rs"), PathBuf::from("src/lib.rs")]
+            );
+            // a trailing `/` only matches directories, with everything inside
+            assert_eq!(files_for(&["bin/"]), vec![PathBuf::from("src/bin/cli.rs")]);
+            assert_eq!(
+                files_for(&["*.rs", "!src/"]),
+                vec![PathBuf::from("main.rs")]
+            );
+        }
+
+        #[test]
+        fn overlapping_roots_should_not_give_duplicate_paths() {
+            let root = TestDir::new("walker-roots");
+            let nested = root.join("nested");
+            std::fs::create_dir_all(&nested).unwrap();
+            std::fs::write(root.join("top.rs"), "").unwrap();
+            std::fs::write(root.join("notes.txt"), "").unwrap();
+            std::fs::write(nested.join("inner.rs"), "").unwrap();
+
+            let notes = root.join("notes.txt");
+            let files = unwrap_and_check_ok(
+                get_file_paths_that_match_expr(
+                    &["*.rs"],
+                    &[&nested, &root, &notes],
+                    &WalkerOptions::default(),
+                ),
+                "result from parsing dirs should not be err",
+            );
+
+            // files given as roots are kept even if they do not match the pattern
+            assert_eq!(
+                files,
+                vec![nested.join("inner.rs"), notes, root.join("top.rs")]
+            );
+        }
     }
 
     mod file_reader {
@@ -84,10 +709,10 @@ mod tests {
         #[test]
         fn data_from_file_should_be_some() {
             let path = Path::new("Cargo.toml");
-            let statement_to_find = " ";
+            let changes_requested = mock_wanted_changes(" ", " ");
 
             let some_lines = unwrap_and_check_ok(
-                file_io::read_file_data_and_check_for_match(&path, statement_to_find),
+                read_file_data_and_check_for_match(path, &changes_requested, false),
                 "reading file data for valid path should not return err",
             );
 
@@ -102,9 +727,10 @@ mod tests {
         fn data_from_file_with_non_match_should_be_none() {
             let path = Path::new("Cargo.toml");
             let nonexistent_statement = "nonexistent_substring".repeat(10);
+            let changes_requested = mock_wanted_changes(&nonexistent_statement, " ");
 
             let lines = unwrap_and_check_ok(
-                file_io::read_file_data_and_check_for_match(&path, &nonexistent_statement),
+                read_file_data_and_check_for_match(path, &changes_requested, false),
                 "reading file data for valid path should not return err",
             );
 
@@ -112,42 +738,186 @@ mod tests {
         }
     }
 
+    mod binary_files {
+        use super::*;
+        use crate::test_dir::TestDir;
+
+        #[test]
+        fn binary_files_should_be_skipped_unless_searched() {
+            let dir = TestDir::new("binary-nul");
+            let file_path = dir.file(b"old\0data\n");
+            let changes_requested = mock_wanted_changes("old", "new");
+
+            let skipped = read_file_data_and_check_for_match(&file_path, &changes_requested, false);
+            let searched = read_file_data_and_check_for_match(&file_path, &changes_requested, true);
+
+            assert!(skipped.unwrap().is_none());
+            assert!(searched.unwrap().is_some());
+        }
+
+        #[test]
+        fn invalid_utf8_should_be_err_instead_of_panicking() {
+            let dir = TestDir::new("binary-latin1");
+            let file_path = dir.file(b"old caf\xe9\n");
+            let changes_requested = mock_wanted_changes("old", "new");
+
+            let result = read_file_data_and_check_for_match(&file_path, &changes_requested, true);
+
+            assert_eq!(result.err().unwrap().kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    mod line_endings {
+        use super::*;
+
+        fn round_trip(text: &str) -> String {
+            let changes_requested = mock_wanted_changes("old", "new");
+            let file_data = FileData::from_text(Path::new("mock.txt"), text, &changes_requested)
+                .expect("text should contain the term");
+            let changes = FileChanges::from_file_data(
+                &file_data,
+                &changes_requested,
+                ContextLines::default(),
+            );
+            apply_changes(&file_data, &changes)
+        }
+
+        #[test]
+        fn trailing_newline_should_be_kept() {
+            assert_eq!(round_trip("a\nold\nb\n"), "a\nnew\nb\n");
+            assert_eq!(round_trip("a\nold"), "a\nnew");
+        }
+
+        #[test]
+        fn crlf_and_mixed_line_endings_should_be_kept() {
+            assert_eq!(round_trip("old\r\nb\r\n"), "new\r\nb\r\n");
+            assert_eq!(round_trip("a\r\nold\nb\r\n"), "a\r\nnew\nb\r\n");
+        }
+
+        #[test]
+        fn bom_should_be_kept_and_not_be_part_of_the_first_line() {
+            let changes_requested = mock_wanted_changes("old", "new");
+            let file_data =
+                FileData::from_text(Path::new("mock.txt"), "\u{feff}old\n", &changes_requested)
+                    .unwrap();
+
+            assert!(file_data.bom);
+            assert_eq!(file_data.contents, vec!["old"]);
+            assert_eq!(round_trip("\u{feff}old\n"), "\u{feff}new\n");
+        }
+    }
+
+    mod file_writer {
+        use super::*;
+        use crate::test_dir::TestDir;
+
+        fn dir_entry_names(dir: &Path) -> Vec<String> {
+            let mut names = fs::read_dir(dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+                .collect::<Vec<String>>();
+            names.sort();
+            names
+        }
+
+        #[test]
+        fn atomic_write_should_replace_contents_without_leftovers() {
+            let dir = TestDir::new("writer-contents");
+            let file_path = dir.join("file.txt");
+            fs::write(&file_path, "old contents").unwrap();
+
+            write_file_atomically(&file_path, b"new", HardLinkPolicy::default()).unwrap();
+
+            assert_eq!(fs::read_to_string(&file_path).unwrap(), "new");
+            assert_eq!(dir_entry_names(&dir), vec!["file.txt"]);
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn atomic_write_should_keep_permissions() {
+            use std::os::unix::fs::PermissionsExt;
+            let dir = TestDir::new("writer-permissions");
+            let file_path = dir.join("script.sh");
+            fs::write(&file_path, "echo old").unwrap();
+            fs::set_permissions(&file_path, fs::Permissions::from_mode(0o750)).unwrap();
+
+            write_file_atomically(&file_path, b"echo new", HardLinkPolicy::default()).unwrap();
+
+            let mode = fs::metadata(&file_path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o750);
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn symlinks_should_be_written_through() {
+            let dir = TestDir::new("writer-symlink");
+            let file_path = dir.join("target.txt");
+            let link_path = dir.join("link.txt");
+            fs::write(&file_path, "old").unwrap();
+            std::os::unix::fs::symlink(&file_path, &link_path).unwrap();
+
+            write_file_atomically(&link_path, b"new", HardLinkPolicy::default()).unwrap();
+
+            assert!(fs::symlink_metadata(&link_path)
+                .unwrap()
+                .file_type()
+                .is_symlink());
+            assert_eq!(fs::read_to_string(&file_path).unwrap(), "new");
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn hard_links_should_follow_the_policy() {
+            let dir = TestDir::new("writer-hard-link");
+            let file_path = dir.join("file.txt");
+            let link_path = dir.join("link.txt");
+            fs::write(&file_path, "old").unwrap();
+            fs::hard_link(&file_path, &link_path).unwrap();
+
+            write_file_atomically(&file_path, b"shared", HardLinkPolicy::WriteThrough).unwrap();
+            assert_eq!(fs::read_to_string(&link_path).unwrap(), "shared");
+
+            write_file_atomically(&file_path, b"replaced", HardLinkPolicy::Replace).unwrap();
+            assert_eq!(fs::read_to_string(&file_path).unwrap(), "replaced");
+            assert_eq!(fs::read_to_string(&link_path).unwrap(), "shared");
+        }
+    }
+
     mod file_changes_tests {
         use super::file_changes::ParsedLine;
         use super::*;
-        use ansi_term::Color;
         use std::collections::HashSet;
 
-        fn mock_wanted_changes(old: &str, new: &str) -> file_changes::WantedChanges {
-            file_changes::WantedChanges {
-                old: old.to_string(),
-                new: new.to_string(),
-            }
-        }
-
         #[test]
         fn should_be_able_to_create_from_file_data() {
             let changes_requested = mock_wanted_changes(" ", " ");
-            let file_data = valid_file_data(&changes_requested.old);
-            let changes = FileChanges::from_file_data(&file_data, &changes_requested);
+            let file_data = valid_file_data(&changes_requested);
+            let changes = FileChanges::from_file_data(
+                &file_data,
+                &changes_requested,
+                ContextLines::default(),
+            );
 
-            assert!(!changes.lines.is_empty());
+            assert!(!changes.is_empty());
         }
 
         #[test]
         fn number_of_lines_with_term_should_match() {
             // "[package]" is only present on one line in Cargo.toml by definition
             let changes_requested = mock_wanted_changes("[package]", " ");
-            let file_data = valid_file_data(&changes_requested.old);
-            let changes = FileChanges::from_file_data(&file_data, &changes_requested);
+            let file_data = valid_file_data(&changes_requested);
+            let changes = FileChanges::from_file_data(
+                &file_data,
+                &changes_requested,
+                ContextLines::default(),
+            );
 
-            assert!(!changes.lines.is_empty());
+            assert!(!changes.is_empty());
             assert_eq!(
                 changes
-                    .lines
-                    .into_iter()
-                    .filter(|line| line.has_term)
-                    .collect::<Vec<ParsedLine>>()
+                    .lines()
+                    .filter(|line| line.has_term())
+                    .collect::<Vec<&ParsedLine>>()
                     .len(),
                 1,
                 "should be exactly one matching element in list"
@@ -158,50 +928,386 @@ mod tests {
         fn changes_should_represent_old_and_new_terms() {
             let old = "=";
             let new = "+";
-            let changes_requested = mock_wanted_changes(&old, &new);
-            let file_data = valid_file_data(&changes_requested.old);
-            let changes = FileChanges::from_file_data(&file_data, &changes_requested);
+            let changes_requested = mock_wanted_changes(old, new);
+            let file_data = valid_file_data(&changes_requested);
+            let changes = FileChanges::from_file_data(
+                &file_data,
+                &changes_requested,
+                ContextLines::default(),
+            );
 
-            assert!(!changes.lines.is_empty());
+            assert!(!changes.is_empty());
             assert!(
-                changes.lines.iter().all(|line| {
-                    if let Some(new_term) = &line.contents.new.as_ref() {
-                        let old_is_ok = line.contents.old.contains(&old);
-                        let new_is_ok = new_term.1
-                            == line
-                                .contents
-                                .old
-                                .replace(&Color::Red.paint(old).to_string(), &new);
-                        println!(
-                            ";;;: {}, {}",
-                            new_term.1,
-                            line.contents.old.replace(&old, &new)
-                        );
-                        old_is_ok && new_is_ok
-                    } else {
-                        true
-                    }
+                changes.lines().all(|line| {
+                    let old_is_ok = line.spans.iter().all(|span| {
+                        &line.text[span.start..span.end()] == old && span.replacement == new
+                    });
+                    let new_is_ok =
+                        !line.has_term() || line.new_text() == line.text.replace(old, new);
+                    old_is_ok && new_is_ok
                 }),
                 "old and new terms from changes should match wanted changes"
             );
         }
 
+        #[test]
+        fn regex_changes_should_expand_capture_groups() {
+            let changes_requested = WantedChanges::new(
+                r"(?P<key>\w+) = (\S+)".to_string(),
+                "${key}: $2".to_string(),
+                MatchOptions {
+                    regex: true,
+                    ..MatchOptions::default()
+                },
+            )
+            .unwrap();
+
+            assert!(changes_requested.is_match("name = \"turbogrep\""));
+            assert_eq!(
+                changes_requested.replace_all("name = \"turbogrep\""),
+                "name: \"turbogrep\""
+            );
+        }
+
+        #[test]
+        fn ignore_case_should_replace_every_matched_span() {
+            let changes_requested = WantedChanges::new(
+                "straße".to_string(),
+                "road".to_string(),
+                MatchOptions {
+                    case: file_changes::CaseSensitivity::Insensitive,
+                    ..MatchOptions::default()
+                },
+            )
+            .unwrap();
+
+            assert_eq!(
+                changes_requested.replace_all("Straße, STRASSE, STRAẞE"),
+                "road, STRASSE, road"
+            );
+        }
+
+        #[test]
+        fn smart_case_should_be_sensitive_only_with_uppercase_letters() {
+            let smart_case = |old: &str, regex| {
+                WantedChanges::new(
+                    old.to_string(),
+                    "x".to_string(),
+                    MatchOptions {
+                        regex,
+                        case: file_changes::CaseSensitivity::Smart,
+                        ..MatchOptions::default()
+                    },
+                )
+                .unwrap()
+            };
+
+            assert!(smart_case("foo", false).is_match("FOO"));
+            assert!(!smart_case("Foo", false).is_match("FOO"));
+            // escape sequences of a regex are not letters of the term
+            assert!(smart_case(r"foo\W", true).is_match("FOO!"));
+            assert!(!smart_case(r"Foo\W", true).is_match("FOO!"));
+        }
+
+        #[test]
+        fn word_boundaries_should_leave_longer_words_alone() {
+            let changes_requested = WantedChanges::new(
+                "id".to_string(),
+                "user_id".to_string(),
+                MatchOptions {
+                    word: Some(file_changes::WordChars::default()),
+                    ..MatchOptions::default()
+                },
+            )
+            .unwrap();
+
+            assert!(!changes_requested.is_match("width, valid, identity"));
+            assert_eq!(
+                changes_requested.replace_all("f(id, width, $id, idé, id)"),
+                "f(user_id, width, $user_id, idé, user_id)"
+            );
+        }
+
+        #[test]
+        fn extra_word_chars_should_be_part_of_identifiers() {
+            let changes_requested = WantedChanges::new(
+                "ab".to_string(),
+                "x".to_string(),
+                MatchOptions {
+                    word: file_changes::WordChars::for_language("css"),
+                    ..MatchOptions::default()
+                },
+            )
+            .unwrap();
+
+            // the rejected "ab" in "-ab" must not hide the one that follows
+            assert_eq!(changes_requested.replace_all("ab-ab ab ab"), "ab-ab x x");
+        }
+
+        #[test]
+        fn preserve_case_should_replace_every_variant_with_the_same_variant() {
+            let changes_requested = WantedChanges::new(
+                "foo_bar".to_string(),
+                "baz_qux".to_string(),
+                MatchOptions {
+                    preserve_case: true,
+                    ..MatchOptions::default()
+                },
+            )
+            .unwrap();
+
+            assert_eq!(
+                changes_requested.replace_all("foo_bar FooBar FOO_BAR fooBar foo-bar Foo_bar"),
+                "baz_qux BazQux BAZ_QUX bazQux baz-qux Foo_bar"
+            );
+            let spans = changes_requested.match_spans("FooBar", 0);
+            assert_eq!(
+                spans[0].case_variant,
+                Some(crate::preserve_case::CaseVariant::Pascal)
+            );
+        }
+
+        #[test]
+        fn multiline_changes_should_span_and_add_lines() {
+            let changes_requested = WantedChanges::new(
+                r"fn (\w+)\(\s*(\w+): u8,\s*\)".to_string(),
+                "fn $1(\n    $2: u16,\n    extra: bool,\n)".to_string(),
+                MatchOptions {
+                    regex: true,
+                    multiline: true,
+                    ..MatchOptions::default()
+                },
+            )
+            .unwrap();
+            let file_data = FileData::from_text(
+                Path::new("mock.rs"),
+                "// a\r\nfn f(\r\n    x: u8,\r\n) {}\r\n",
+                &changes_requested,
+            )
+            .unwrap();
+            assert_eq!(file_data.term_spans, vec![(1, 3)]);
+            assert_eq!(file_data.term_containing_lines, vec![1, 2, 3]);
+
+            let changes = FileChanges::from_file_data(
+                &file_data,
+                &changes_requested,
+                ContextLines::default(),
+            );
+            let changed_line = changes.lines().find(|line| line.has_term()).unwrap();
+            assert_eq!((changed_line.num, changed_line.line_count), (1, 3));
+            // the added lines get the line ending of the lines they replace
+            assert_eq!(
+                apply_changes(&file_data, &changes),
+                "// a\r\nfn f(\r\n    x: u16,\r\n    extra: bool,\r\n) {}\r\n"
+            );
+        }
+
+        #[test]
+        fn multiline_matches_ending_with_a_newline_should_join_the_next_line() {
+            let changes_requested = WantedChanges::new(
+                "b\n".to_string(),
+                "".to_string(),
+                MatchOptions {
+                    multiline: true,
+                    ..MatchOptions::default()
+                },
+            )
+            .unwrap();
+            let file_data =
+                FileData::from_text(Path::new("mock.txt"), "a\nb\nc\n", &changes_requested)
+                    .unwrap();
+            let changes = FileChanges::from_file_data(
+                &file_data,
+                &changes_requested,
+                ContextLines::default(),
+            );
+
+            assert_eq!(file_data.term_spans, vec![(1, 2)]);
+            assert_eq!(apply_changes(&file_data, &changes), "a\nc\n");
+        }
+
+        #[test]
+        fn multiline_matches_should_take_the_final_newline_of_the_file() {
+            let multiline_changes = |old: &str, new: &str| {
+                WantedChanges::new(
+                    old.to_string(),
+                    new.to_string(),
+                    MatchOptions {
+                        multiline: true,
+                        ..MatchOptions::default()
+                    },
+                )
+                .unwrap()
+            };
+            let replaced = |text: &str, old: &str, new: &str| {
+                replace_in_text(Path::new("mock.txt"), text, &multiline_changes(old, new))
+            };
+
+            assert_eq!(replaced("foo\nbar\n", "bar\n", ""), "foo\n");
+            assert_eq!(replaced("foo\r\nbar\r\n", "bar\n", ""), "foo\r\n");
+            assert_eq!(replaced("foo\nbar\n", "bar\n", "baz\n"), "foo\nbaz\n");
+            assert_eq!(replaced("foo\nbar\n", "bar\n", "baz"), "foo\nbaz");
+            assert_eq!(replaced("foo\nbar\n", "foo\nbar\n", ""), "");
+            // without a final newline there is none to match
+            assert_eq!(replaced("foo\nbar", "bar\n", ""), "foo\nbar");
+
+            let file_data = FileData::from_text(
+                Path::new("mock.txt"),
+                "foo\nbar\n",
+                &multiline_changes("bar\n", ""),
+            )
+            .unwrap();
+            assert_eq!(file_data.term_spans, vec![(1, 1)]);
+        }
+
+        #[test]
+        fn multiline_empty_matches_should_not_be_found_in_empty_files() {
+            let changes_requested = WantedChanges::new(
+                "x*".to_string(),
+                "y".to_string(),
+                MatchOptions {
+                    regex: true,
+                    multiline: true,
+                    ..MatchOptions::default()
+                },
+            )
+            .unwrap();
+
+            assert!(FileData::from_text(Path::new("mock.txt"), "", &changes_requested).is_none());
+            assert_eq!(
+                replace_in_text(Path::new("mock.txt"), "", &changes_requested),
+                ""
+            );
+        }
+
+        #[test]
+        fn match_spans_should_have_byte_offsets_and_char_columns() {
+            let changes_requested = mock_wanted_changes("id", "key");
+            let spans = changes_requested.match_spans("é(id, x)\n  id", 7);
+
+            assert_eq!(spans.len(), 2);
+            assert_eq!((spans[0].start, spans[0].len), (3, 2));
+            assert_eq!((spans[0].line, spans[0].column), (7, 2));
+            assert_eq!((spans[1].start, spans[1].line, spans[1].column), (12, 8, 2));
+            assert_eq!(spans[1].replacement, "key");
+        }
+
+        #[test]
+        fn literal_changes_should_not_expand_capture_groups() {
+            let changes_requested = mock_wanted_changes("(a)", "$1");
+
+            assert!(!changes_requested.is_match("a"));
+            assert_eq!(changes_requested.replace_all("x(a)x"), "x$1x");
+        }
+
+        #[test]
+        fn context_lines_should_limit_the_lines_around_each_match() {
+            let changes_requested = mock_wanted_changes("old", "new");
+            let file_data = FileData::from_text(
+                Path::new("mock.txt"),
+                "old 0\n1\n2\n3\nold 4\n5\n6\n",
+                &changes_requested,
+            )
+            .unwrap();
+            let line_nums = |context| {
+                FileChanges::from_file_data(&file_data, &changes_requested, context)
+                    .lines()
+                    .map(|line| line.num)
+                    .collect::<Vec<usize>>()
+            };
+
+            assert_eq!(
+                line_nums(ContextLines {
+                    before: 0,
+                    after: 0
+                }),
+                vec![0, 4]
+            );
+            assert_eq!(
+                line_nums(ContextLines {
+                    before: 1,
+                    after: 2
+                }),
+                vec![0, 1, 2, 3, 4, 5, 6]
+            );
+            assert_eq!(
+                line_nums(ContextLines {
+                    before: 0,
+                    after: 1
+                }),
+                vec![0, 1, 4, 5]
+            );
+        }
+
+        #[test]
+        fn overlapping_or_touching_context_should_merge_into_one_hunk() {
+            let changes_requested = mock_wanted_changes("old", "new");
+            let file_data = FileData::from_text(
+                Path::new("mock.txt"),
+                "old 0\n1\nold 2\n3\n4\nold 5\n6\n7\n8\nold 9\n",
+                &changes_requested,
+            )
+            .unwrap();
+            let changes = FileChanges::from_file_data(
+                &file_data,
+                &changes_requested,
+                ContextLines {
+                    before: 1,
+                    after: 1,
+                },
+            );
+
+            let hunk_ranges = changes
+                .hunks
+                .iter()
+                .map(|hunk| (hunk.start(), hunk.lines.len()))
+                .collect::<Vec<(usize, usize)>>();
+            assert_eq!(hunk_ranges, vec![(0, 7), (8, 2)]);
+            assert_eq!(
+                crate::preview::render_changes(&changes)
+                    .matches("\n--\n")
+                    .count(),
+                1
+            );
+        }
+
         #[test]
         fn should_not_have_any_duplicate_lines() {
             let changes_requested = mock_wanted_changes(" ", " ");
-            let file_data = valid_file_data(&changes_requested.old);
-            let changes = FileChanges::from_file_data(&file_data, &changes_requested);
+            let file_data = valid_file_data(&changes_requested);
+            let changes = FileChanges::from_file_data(
+                &file_data,
+                &changes_requested,
+                ContextLines::default(),
+            );
 
-            assert!(!changes.lines.is_empty());
+            assert!(!changes.is_empty());
             let mut line_set = HashSet::new();
             let all_lines_inserted_non_dupe = changes
-                .lines
-                .into_iter()
+                .lines()
                 // HashSet.insert() returns false if no insert happened,
                 // meaning that there was a duplicate entry.
                 .all(|line| line_set.insert(line));
             assert!(all_lines_inserted_non_dupe);
         }
+
+        #[test]
+        fn replaced_text_should_keep_everything_but_the_matches() {
+            let changes_requested = mock_wanted_changes("old", "new");
+
+            assert_eq!(
+                replace_in_text(
+                    Path::new("-"),
+                    "\u{feff}old\r\nkept\nold old",
+                    &changes_requested
+                ),
+                "\u{feff}new\r\nkept\nnew new"
+            );
+            assert_eq!(
+                replace_in_text(Path::new("-"), "kept\n", &changes_requested),
+                "kept\n"
+            );
+        }
     }
 
     fn unwrap_and_check_ok<T>(result: io::Result<T>, assert_msg: &str) -> T {
@@ -209,75 +1315,13 @@ mod tests {
         result.unwrap()
     }
 
-    fn valid_file_data(term: &str) -> FileData {
+    fn valid_file_data(changes_requested: &WantedChanges) -> FileData {
         let path = Path::new("Cargo.toml");
 
         unwrap_and_check_ok(
-            file_io::read_file_data_and_check_for_match(&path, term),
+            read_file_data_and_check_for_match(path, changes_requested, false),
             "reading file data for valid path should not return err",
         )
         .expect("should not be none with valid path and term")
     }
 }
-
-pub struct FileData {
-    pub file_path: PathBuf,
-    pub contents: Vec<String>,
-    pub term_containing_lines: Vec<usize>,
-}
-mod file_io {
-    use super::{FileChanges, FileData};
-    use std::fs::{self, File};
-    use std::io::{self, BufRead};
-    use std::path::Path;
-
-    pub fn read_file_data_and_check_for_match(
-        file_path: &Path,
-        statement_to_find: &str,
-    ) -> io::Result<Option<FileData>> {
-        let file = File::open(file_path)?;
-        let mut has_match = false;
-        let mut contents = vec![];
-        let mut term_containing_lines = vec![];
-
-        let mut line_num = 0;
-        for line in io::BufReader::new(file).lines().map(|x| x.unwrap()) {
-            if line.contains(statement_to_find) {
-                term_containing_lines.push(line_num);
-                has_match = true;
-            }
-            contents.push(line);
-            line_num += 1;
-        }
-
-        Ok(match has_match {
-            false => None,
-            true => Some(FileData {
-                file_path: file_path.to_path_buf(),
-                term_containing_lines,
-                contents,
-            }),
-        })
-    }
-
-    pub fn execute_changes_to_file(
-        mut file_data: FileData,
-        changes: FileChanges,
-    ) -> io::Result<()> {
-        changes
-            .lines
-            .into_iter()
-            .filter(|line| line.has_term)
-            .map(|line| (line.num, line.contents))
-            .for_each(|x| {
-                let num = x.0;
-                let replaced_line = x.1.new.unwrap();
-                file_data.contents[num] = replaced_line.1;
-            });
-
-        // write file data
-        let contents = file_data.contents.join("\n");
-        fs::write(file_data.file_path, contents.as_bytes())?;
-        Ok(())
-    }
-}
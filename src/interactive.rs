@@ -0,0 +1,343 @@
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use turbogrep::file_changes::{FileChanges, Hunk};
+use turbogrep::preview;
+
+const PROMPT_HELP: &str = "y - apply this hunk
+n - do not apply this hunk
+a - apply this hunk and all remaining hunks in the file
+q - quit; do not apply this hunk or any remaining ones
+e - manually edit the new lines of the hunk before applying it
+? - print help";
+
+#[derive(Debug, Eq, PartialEq)]
+enum Answer {
+    Yes,
+    No,
+    All,
+    Quit,
+    Edit,
+}
+
+impl Answer {
+    fn from_input(input: &str) -> Option<Self> {
+        match input.trim() {
+            "y" | "Y" => Some(Self::Yes),
+            "n" | "N" => Some(Self::No),
+            "a" | "A" => Some(Self::All),
+            "q" | "Q" => Some(Self::Quit),
+            "e" | "E" => Some(Self::Edit),
+            _ => None,
+        }
+    }
+}
+
+/// The changes of a file the user accepted, and whether they asked to stop
+/// processing any further files.
+pub struct Selection {
+    pub changes: FileChanges,
+    pub quit: bool,
+}
+
+pub fn select_changes(file_path: &Path, changes: FileChanges) -> io::Result<Selection> {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    select_changes_from(
+        file_path,
+        changes,
+        &mut stdin.lock(),
+        &mut stdout.lock(),
+        &edit_text_in_editor,
+    )
+}
+
+fn select_changes_from<R, W>(
+    file_path: &Path,
+    changes: FileChanges,
+    input: &mut R,
+    output: &mut W,
+    edit_text: &dyn Fn(&str) -> io::Result<String>,
+) -> io::Result<Selection>
+where
+    R: BufRead,
+    W: Write,
+{
+    let hunk_count = changes.hunks.len();
+    let mut accepted = vec![];
+    let mut apply_all = false;
+    let mut quit = false;
+    for (index, mut hunk) in changes.hunks.into_iter().enumerate() {
+        if quit {
+            break;
+        }
+        if apply_all {
+            accepted.push(hunk);
+            continue;
+        }
+
+        writeln!(output, "{}", preview::render_hunk(&hunk))?;
+        loop {
+            write!(
+                output,
+                "({}/{}) Apply this hunk to {:?} [y,n,a,q,e,?]? ",
+                index + 1,
+                hunk_count,
+                file_path
+            )?;
+            output.flush()?;
+
+            let mut response = String::new();
+            // a closed input is treated the same as the user quitting
+            let answer = match input.read_line(&mut response)? {
+                0 => Answer::Quit,
+                _ => match Answer::from_input(&response) {
+                    Some(answer) => answer,
+                    None => {
+                        writeln!(output, "{}", PROMPT_HELP)?;
+                        continue;
+                    }
+                },
+            };
+
+            match answer {
+                Answer::Yes => accepted.push(hunk),
+                Answer::No => {}
+                Answer::All => {
+                    accepted.push(hunk);
+                    apply_all = true;
+                }
+                Answer::Quit => quit = true,
+                Answer::Edit => {
+                    if let Err(details) = edit_hunk(&mut hunk, edit_text)? {
+                        writeln!(output, "{}", details)?;
+                        continue;
+                    }
+                    accepted.push(hunk);
+                }
+            }
+            break;
+        }
+    }
+
+    Ok(Selection {
+        changes: FileChanges { hunks: accepted },
+        quit,
+    })
+}
+
+/// Lets the user edit the new lines of the hunk, one per line of text. The
+/// hunk is left untouched if the edited text has a different number of lines.
+fn edit_hunk(
+    hunk: &mut Hunk,
+    edit_text: &dyn Fn(&str) -> io::Result<String>,
+) -> io::Result<Result<(), String>> {
+    let new_lines = hunk
+        .changed_lines()
+        .flat_map(|line| line.new_lines().0)
+        .collect::<Vec<String>>();
+    let edited = edit_text(&new_lines.join("\n"))?;
+    let mut edited_lines = edited.lines();
+    if edited.lines().count() != new_lines.len() {
+        return Ok(Err(format!(
+            "the edited hunk must have {} line(s), found {}",
+            new_lines.len(),
+            edited.lines().count()
+        )));
+    }
+
+    // a multi-line change takes as many of the edited lines as it had
+    hunk.lines
+        .iter_mut()
+        .filter(|line| line.has_term())
+        .for_each(|line| {
+            let line_count = line.new_lines().0.len();
+            // a line the change removes has nothing to edit
+            if line_count == 0 {
+                return;
+            }
+            let edited = edited_lines
+                .by_ref()
+                .take(line_count)
+                .collect::<Vec<&str>>()
+                .join("\n");
+            line.set_new_text(edited)
+        });
+    Ok(Ok(()))
+}
+
+fn edit_text_in_editor(text: &str) -> io::Result<String> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let (edit_path, mut edit_file) = create_edit_file()?;
+    let written = edit_file.write_all(format!("{}\n", text).as_bytes());
+    drop(edit_file);
+    if let Err(error) = written {
+        fs::remove_file(&edit_path)?;
+        return Err(error);
+    }
+
+    let status = Command::new(&editor).arg(&edit_path).status();
+    let edited = fs::read_to_string(&edit_path);
+    fs::remove_file(&edit_path)?;
+
+    if !status?.success() {
+        return Err(io::Error::other(format!(
+            "editor \"{}\" exited with an error",
+            editor
+        )));
+    }
+    Ok(edited?.trim_end_matches(&['\r', '\n'][..]).to_string())
+}
+
+/// Creates a new file in the temp dir for the text to edit. The temp dir is
+/// shared, so a file (or symlink) already there under the same name is never
+/// opened, the next name is tried instead.
+fn create_edit_file() -> io::Result<(PathBuf, File)> {
+    const MAX_ATTEMPTS: usize = 100;
+    for attempt in 0..MAX_ATTEMPTS {
+        let edit_path = env::temp_dir().join(format!(
+            "turbogrep-edit-{}-{}.txt",
+            std::process::id(),
+            attempt
+        ));
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&edit_path)
+        {
+            Ok(edit_file) => return Ok((edit_path, edit_file)),
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(error) => return Err(error),
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        "could not create a file to edit the hunk in the temp dir",
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+    use turbogrep::file_changes::{ContextLines, MatchOptions, WantedChanges};
+    use turbogrep::file_io::FileData;
+
+    fn mock_changes() -> FileChanges {
+        let changes_requested = WantedChanges::new(
+            "old".to_string(),
+            "new".to_string(),
+            MatchOptions::default(),
+        )
+        .unwrap();
+        let file_data = FileData::from_text(
+            Path::new("mock.txt"),
+            "old one\ntwo\nthree\nfour\nfive\nsix\nold seven\n",
+            &changes_requested,
+        )
+        .unwrap();
+        FileChanges::from_file_data(&file_data, &changes_requested, ContextLines::default())
+    }
+
+    fn select_with_input(input: &str) -> Selection {
+        let no_edit = |_: &str| -> io::Result<String> { panic!("editor should not be opened") };
+        select_changes_from(
+            Path::new("mock.txt"),
+            mock_changes(),
+            &mut Cursor::new(input.as_bytes()),
+            &mut vec![],
+            &no_edit,
+        )
+        .unwrap()
+    }
+
+    fn accepted_nums(selection: &Selection) -> Vec<usize> {
+        selection
+            .changes
+            .lines()
+            .filter(|line| line.has_term())
+            .map(|line| line.num)
+            .collect()
+    }
+
+    #[test]
+    fn only_accepted_hunks_should_be_kept() {
+        let selection = select_with_input("n\ny\n");
+
+        assert_eq!(accepted_nums(&selection), vec![6]);
+        assert!(!selection.quit);
+    }
+
+    #[test]
+    fn accepting_all_should_keep_remaining_hunks() {
+        let selection = select_with_input("a\n");
+
+        assert_eq!(accepted_nums(&selection), vec![0, 6]);
+    }
+
+    #[test]
+    fn quitting_should_stop_asking_and_keep_previous_answers() {
+        let selection = select_with_input("y\nq\n");
+
+        assert_eq!(accepted_nums(&selection), vec![0]);
+        assert!(selection.quit);
+    }
+
+    #[test]
+    fn unknown_answers_should_ask_again() {
+        let selection = select_with_input("x\n\ny\nn\n");
+
+        assert_eq!(accepted_nums(&selection), vec![0]);
+    }
+
+    #[test]
+    fn edited_hunks_should_replace_the_new_lines() {
+        let edit = |line: &str| -> io::Result<String> { Ok(format!("{}!", line)) };
+        let selection = select_changes_from(
+            Path::new("mock.txt"),
+            mock_changes(),
+            &mut Cursor::new("e\nn\n".as_bytes()),
+            &mut vec![],
+            &edit,
+        )
+        .unwrap();
+
+        let line = selection
+            .changes
+            .lines()
+            .find(|line| line.has_term())
+            .unwrap();
+        assert_eq!(line.num, 0);
+        assert_eq!(line.new_text(), "new one!");
+    }
+
+    #[test]
+    fn edits_with_a_different_line_count_should_ask_again() {
+        let edit = |text: &str| -> io::Result<String> { Ok(format!("{}\nextra", text)) };
+        let selection = select_changes_from(
+            Path::new("mock.txt"),
+            mock_changes(),
+            &mut Cursor::new("e\nn\nn\n".as_bytes()),
+            &mut vec![],
+            &edit,
+        )
+        .unwrap();
+
+        assert_eq!(accepted_nums(&selection), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn edit_files_should_not_open_paths_that_already_exist() {
+        let (first_path, first_file) = create_edit_file().unwrap();
+        let (second_path, second_file) = create_edit_file().unwrap();
+        drop((first_file, second_file));
+        fs::remove_file(&first_path).unwrap();
+        fs::remove_file(&second_path).unwrap();
+
+        assert_ne!(first_path, second_path);
+    }
+}
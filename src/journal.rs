@@ -0,0 +1,611 @@
+use super::file_io::{HardLinkPolicy, ReplacementFile};
+use super::path_renames::{self, PathRename};
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MANIFEST_FILENAME: &str = "manifest";
+const RENAMES_FILENAME: &str = "renames";
+const COPY_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Records the original contents of every file changed during a run, so that
+/// the run can be reverted with `turbogrep undo`.
+///
+/// Each run gets its own directory under the state directory, holding one blob
+/// per file with its original contents and a manifest with one line per file:
+/// `<original hash>\t<new hash>\t<blob name>\t<absolute path>`. The paths
+/// renamed during the run are listed in a separate file, in the order they
+/// were renamed, with the absolute path before and after each rename on two
+/// lines of their own.
+pub struct Journal {
+    pub run_id: String,
+    run_dir: PathBuf,
+    manifest: Mutex<(File, usize)>,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+struct JournalEntry {
+    original_hash: u64,
+    new_hash: u64,
+    blob_name: String,
+    file_path: PathBuf,
+}
+
+impl JournalEntry {
+    fn to_line(&self) -> String {
+        format!(
+            "{:016x}\t{:016x}\t{}\t{}\n",
+            self.original_hash,
+            self.new_hash,
+            self.blob_name,
+            self.file_path.display()
+        )
+    }
+
+    fn from_line(line: &str) -> io::Result<Self> {
+        let malformed = || invalid_data(format!("malformed journal entry: {:?}", line));
+        let mut fields = line.splitn(4, '\t');
+        let mut next_field = || fields.next().ok_or_else(malformed);
+        let parse_hash = |field: &str| u64::from_str_radix(field, 16).map_err(|_| malformed());
+        Ok(Self {
+            original_hash: parse_hash(next_field()?)?,
+            new_hash: parse_hash(next_field()?)?,
+            blob_name: next_field()?.to_string(),
+            file_path: PathBuf::from(next_field()?),
+        })
+    }
+}
+
+impl Journal {
+    pub fn create(state_dir: &Path) -> io::Result<Self> {
+        let elapsed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(io::Error::other)?;
+        // zero-padded so that the run ids sort in the order the runs happened
+        let run_id = format!(
+            "{:012}{:09}-{}",
+            elapsed.as_secs(),
+            elapsed.subsec_nanos(),
+            std::process::id()
+        );
+        let run_dir = runs_dir(state_dir).join(&run_id);
+        fs::create_dir_all(&run_dir)?;
+        let manifest = File::create(run_dir.join(MANIFEST_FILENAME))?;
+
+        Ok(Self {
+            run_id,
+            run_dir,
+            manifest: Mutex::new((manifest, 0)),
+        })
+    }
+
+    /// Saves the original contents of the file, this has to be called before
+    /// the new contents are written to it.
+    pub fn record(&self, file_path: &Path, original: &[u8], new: &[u8]) -> io::Result<()> {
+        let file_path = fs::canonicalize(file_path)?;
+        let mut manifest = self.manifest.lock().unwrap();
+        let (manifest_file, entry_count) = &mut *manifest;
+
+        let entry = JournalEntry {
+            original_hash: content_hash(original),
+            new_hash: content_hash(new),
+            blob_name: format!("{}.orig", entry_count),
+            file_path,
+        };
+        fs::write(self.run_dir.join(&entry.blob_name), original)?;
+        manifest_file.write_all(entry.to_line().as_bytes())?;
+        manifest_file.sync_all()?;
+        *entry_count += 1;
+        Ok(())
+    }
+
+    /// Same as `record`, for a file too large to hold in memory: its original
+    /// contents are copied from the file itself, so this has to be called
+    /// before the file is replaced, with the hash of its new contents.
+    pub fn record_file(&self, file_path: &Path, new_hash: u64) -> io::Result<()> {
+        let file_path = fs::canonicalize(file_path)?;
+        let mut manifest = self.manifest.lock().unwrap();
+        let (manifest_file, entry_count) = &mut *manifest;
+
+        let blob_name = format!("{}.orig", entry_count);
+        let mut hasher = ContentHasher::default();
+        let mut blob = File::create(self.run_dir.join(&blob_name))?;
+        copy_hashing(&mut File::open(&file_path)?, &mut blob, &mut hasher)?;
+        blob.sync_all()?;
+
+        let entry = JournalEntry {
+            original_hash: hasher.finish(),
+            new_hash,
+            blob_name,
+            file_path,
+        };
+        manifest_file.write_all(entry.to_line().as_bytes())?;
+        manifest_file.sync_all()?;
+        *entry_count += 1;
+        Ok(())
+    }
+
+    /// Saves the renames, this has to be called before they are run. Their
+    /// paths are made absolute, without resolving the renamed paths
+    /// themselves so that a symlink is moved back as a symlink.
+    pub fn record_renames(&self, renames: &[PathRename]) -> io::Result<()> {
+        let mut lines = String::new();
+        for rename in renames {
+            let from = absolute_path(&rename.from)?;
+            let to = match rename.to.file_name() {
+                Some(new_name) => from.with_file_name(new_name),
+                None => return Err(invalid_data(format!("invalid rename to {:?}", rename.to))),
+            };
+            lines.push_str(&format!("{}\n{}\n", from.display(), to.display()));
+        }
+        let mut renames_file = File::create(self.run_dir.join(RENAMES_FILENAME))?;
+        renames_file.write_all(lines.as_bytes())?;
+        renames_file.sync_all()
+    }
+
+    /// Removes the run from the state directory if no file was recorded.
+    pub fn finish(self) -> io::Result<Option<String>> {
+        let (_, entry_count) = self.manifest.into_inner().unwrap();
+        let has_renames = self.run_dir.join(RENAMES_FILENAME).exists();
+        match entry_count == 0 && !has_renames {
+            true => {
+                fs::remove_dir_all(&self.run_dir)?;
+                Ok(None)
+            }
+            false => Ok(Some(self.run_id)),
+        }
+    }
+}
+
+/// The path with its parent directory resolved, the path itself is kept even
+/// if it is a symlink.
+fn absolute_path(path: &Path) -> io::Result<PathBuf> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    match path.file_name() {
+        Some(name) => Ok(fs::canonicalize(parent)?.join(name)),
+        None => fs::canonicalize(path),
+    }
+}
+
+/// Returns the directory the journals are stored under: `$TURBOGREP_STATE_DIR`
+/// if set, otherwise `$XDG_STATE_HOME/turbogrep` or `~/.local/state/turbogrep`.
+pub fn state_dir() -> io::Result<PathBuf> {
+    let non_empty_var = |name: &str| env::var_os(name).filter(|val| !val.is_empty());
+    if let Some(dir) = non_empty_var("TURBOGREP_STATE_DIR") {
+        return Ok(PathBuf::from(dir));
+    }
+    if let Some(dir) = non_empty_var("XDG_STATE_HOME") {
+        return Ok(PathBuf::from(dir).join("turbogrep"));
+    }
+    match non_empty_var("HOME").or_else(|| non_empty_var("USERPROFILE")) {
+        Some(home) => Ok(PathBuf::from(home).join(".local/state/turbogrep")),
+        None => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "could not find a state directory for the undo journal, set $TURBOGREP_STATE_DIR",
+        )),
+    }
+}
+
+/// A run reverted by `undo`.
+#[derive(Debug)]
+pub struct UndoneRun {
+    pub run_id: String,
+    pub restored_paths: Vec<PathBuf>,
+    /// The renames of the run that were moved back, in the order they ran.
+    pub renames: Vec<PathRename>,
+}
+
+/// Reverts the given run (or the latest run if `None`): the renamed paths are
+/// moved back first, in reverse order, and then the files changed get their
+/// original contents back.
+///
+/// Nothing is moved or restored if any of the files was modified after the
+/// run (wherever a rename moved it), or if moving a path back would replace
+/// another. Files that still have their original contents (because writing
+/// them failed) are left as they are, and so are renames that did not run.
+pub fn undo(state_dir: &Path, run_id: Option<&str>) -> io::Result<UndoneRun> {
+    let run_id = match run_id {
+        Some(run_id) => run_id.to_string(),
+        None => latest_run_id(state_dir)?,
+    };
+    let run_dir = runs_dir(state_dir).join(&run_id);
+    let manifest =
+        fs::read_to_string(run_dir.join(MANIFEST_FILENAME)).map_err(|error| {
+            match error.kind() {
+                io::ErrorKind::NotFound => io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no journal found for run \"{}\"", run_id),
+                ),
+                _ => error,
+            }
+        })?;
+    let entries = manifest
+        .lines()
+        .map(JournalEntry::from_line)
+        .collect::<io::Result<Vec<JournalEntry>>>()?;
+    let renames = renames_that_ran(&read_renames(&run_dir)?);
+
+    // the files are checked where the renames moved them
+    let current_paths = entries
+        .iter()
+        .map(|entry| path_renames::renamed_path(&entry.file_path, &renames))
+        .collect::<Vec<PathBuf>>();
+    let current_hashes = current_paths
+        .iter()
+        .map(|current_path| file_hash(current_path).ok())
+        .collect::<Vec<Option<u64>>>();
+    let modified_paths = entries
+        .iter()
+        .zip(&current_hashes)
+        .zip(&current_paths)
+        .filter(|&((entry, &hash), _)| {
+            hash != Some(entry.new_hash) && hash != Some(entry.original_hash)
+        })
+        .map(|(_, current_path)| current_path.display().to_string())
+        .collect::<Vec<String>>();
+    if !modified_paths.is_empty() {
+        return Err(io::Error::other(format!(
+            "refusing to undo run \"{}\", these files were modified or removed since: {}",
+            run_id,
+            modified_paths.join(", ")
+        )));
+    }
+    check_renames_can_move_back(&renames)?;
+    move_renames_back(&renames)?;
+
+    // the originals are copied back without reading them into memory, so
+    // that large files can be restored too
+    let written_entries = entries
+        .into_iter()
+        .zip(current_hashes)
+        .filter(|(entry, hash)| *hash != Some(entry.original_hash))
+        .map(|(entry, _)| entry)
+        .collect::<Vec<JournalEntry>>();
+    for entry in written_entries.iter() {
+        let blob_path = run_dir.join(&entry.blob_name);
+        if file_hash(&blob_path)? != entry.original_hash {
+            return Err(invalid_data(format!(
+                "journal of run \"{}\" is corrupted for {:?}",
+                run_id, entry.file_path
+            )));
+        }
+        let mut replacement = ReplacementFile::create(&entry.file_path, HardLinkPolicy::default())?;
+        io::copy(&mut File::open(&blob_path)?, &mut replacement)?;
+        replacement.commit()?;
+    }
+    fs::remove_dir_all(&run_dir)?;
+
+    let restored_paths = written_entries
+        .into_iter()
+        .map(|entry| entry.file_path)
+        .collect();
+    Ok(UndoneRun {
+        run_id,
+        restored_paths,
+        renames,
+    })
+}
+
+fn read_renames(run_dir: &Path) -> io::Result<Vec<PathRename>> {
+    let text = match fs::read_to_string(run_dir.join(RENAMES_FILENAME)) {
+        Ok(text) => text,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(error) => return Err(error),
+    };
+    let lines = text.lines().collect::<Vec<&str>>();
+    if lines.len() % 2 != 0 {
+        return Err(invalid_data(format!(
+            "malformed renames journal: {:?}",
+            text
+        )));
+    }
+    Ok(lines
+        .chunks(2)
+        .map(|paths| PathRename {
+            from: PathBuf::from(paths[0]),
+            to: PathBuf::from(paths[1]),
+        })
+        .collect())
+}
+
+/// The renames that ran, in order, found by their path being where they moved
+/// it, which for the deeper paths depends on the renames of their directories
+/// that ran after them.
+fn renames_that_ran(renames: &[PathRename]) -> Vec<PathRename> {
+    let mut ran = vec![];
+    for rename in renames.iter().rev() {
+        ran.push(rename.clone());
+        if fs::symlink_metadata(path_renames::renamed_path(&rename.from, &ran)).is_err() {
+            ran.pop();
+        }
+    }
+    ran.reverse();
+    ran
+}
+
+/// Fails if moving any of the renamed paths back would replace a path that
+/// exists, checked before anything is moved.
+fn check_renames_can_move_back(renames: &[PathRename]) -> io::Result<()> {
+    for (index, rename) in renames.iter().enumerate() {
+        // the renames after this one are moved back before it is
+        let taken_path = path_renames::renamed_path(&rename.from, &renames[index + 1..]);
+        if fs::symlink_metadata(&taken_path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!(
+                    "refusing to move {:?} back, {:?} already exists",
+                    path_renames::renamed_path(&rename.from, &renames[index..]),
+                    taken_path
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Moves the renamed paths back, the last rename first so that every path is
+/// back where the journal has it when its own turn comes.
+fn move_renames_back(renames: &[PathRename]) -> io::Result<()> {
+    for rename in renames.iter().rev() {
+        fs::rename(&rename.to, &rename.from)?;
+    }
+    Ok(())
+}
+
+fn latest_run_id(state_dir: &Path) -> io::Result<String> {
+    let runs_dir = runs_dir(state_dir);
+    let run_ids = match fs::read_dir(&runs_dir) {
+        Ok(entries) => entries
+            .map(|entry| Ok(entry?.file_name().to_string_lossy().to_string()))
+            .collect::<io::Result<Vec<String>>>()?,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => vec![],
+        Err(error) => return Err(error),
+    };
+    run_ids.into_iter().max().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no runs to undo in {:?}", runs_dir),
+        )
+    })
+}
+
+fn runs_dir(state_dir: &Path) -> PathBuf {
+    state_dir.join("runs")
+}
+
+fn invalid_data(details: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, details)
+}
+
+fn content_hash(contents: &[u8]) -> u64 {
+    let mut hasher = ContentHasher::default();
+    hasher.update(contents);
+    hasher.finish()
+}
+
+/// The hash of the file, read a chunk at a time.
+fn file_hash(file_path: &Path) -> io::Result<u64> {
+    let mut hasher = ContentHasher::default();
+    copy_hashing(&mut File::open(file_path)?, &mut io::sink(), &mut hasher)?;
+    Ok(hasher.finish())
+}
+
+/// Copies everything from `reader` to `writer`, hashing it on the way.
+fn copy_hashing<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    hasher: &mut ContentHasher,
+) -> io::Result<()> {
+    let mut buffer = [0; COPY_BUFFER_SIZE];
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(read) => read,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        };
+        hasher.update(&buffer[..read]);
+        writer.write_all(&buffer[..read])?;
+    }
+}
+
+/// 64-bit FNV-1a, which is stable across builds unlike the std hashers, and
+/// gives the same hash however the contents are split into chunks.
+pub struct ContentHasher(u64);
+
+impl Default for ContentHasher {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl ContentHasher {
+    pub fn update(&mut self, contents: &[u8]) {
+        self.0 = contents.iter().fold(self.0, |hash, byte| {
+            (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::path_renames;
+    use crate::test_dir::TestDir;
+
+    fn record_change(journal: &Journal, file_path: &Path, original: &str, new: &str) {
+        fs::write(file_path, original).unwrap();
+        journal
+            .record(file_path, original.as_bytes(), new.as_bytes())
+            .unwrap();
+        fs::write(file_path, new).unwrap();
+    }
+
+    #[test]
+    fn entries_should_round_trip_through_the_manifest() {
+        let entry = JournalEntry {
+            original_hash: content_hash(b"old"),
+            new_hash: content_hash(b"new"),
+            blob_name: "0.orig".to_string(),
+            file_path: PathBuf::from("/some/dir/with\ttab.rs"),
+        };
+        let line = entry.to_line();
+
+        assert_eq!(
+            JournalEntry::from_line(line.trim_end_matches('\n')).unwrap(),
+            entry
+        );
+        assert!(JournalEntry::from_line("not an entry").is_err());
+    }
+
+    #[test]
+    fn undo_should_restore_the_latest_run() {
+        let dir = TestDir::new("journal-restore");
+        let state_dir = dir.join("state");
+        let file_path = dir.join("file.txt");
+
+        let first_run = Journal::create(&state_dir).unwrap();
+        record_change(&first_run, &file_path, "one", "two");
+        first_run.finish().unwrap();
+        let second_run = Journal::create(&state_dir).unwrap();
+        record_change(&second_run, &file_path, "two", "three");
+        let second_run_id = second_run.finish().unwrap().unwrap();
+
+        let undone_run = undo(&state_dir, None).unwrap();
+        assert_eq!(undone_run.run_id, second_run_id);
+        assert_eq!(
+            undone_run.restored_paths,
+            vec![fs::canonicalize(&file_path).unwrap()]
+        );
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), "two");
+
+        undo(&state_dir, None).unwrap();
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), "one");
+        assert!(undo(&state_dir, None).is_err());
+    }
+
+    #[test]
+    fn undo_should_refuse_when_files_were_modified_since_the_run() {
+        let dir = TestDir::new("journal-modified");
+        let state_dir = dir.join("state");
+        let file_path = dir.join("file.txt");
+
+        let journal = Journal::create(&state_dir).unwrap();
+        record_change(&journal, &file_path, "old", "new");
+        let run_id = journal.finish().unwrap().unwrap();
+        fs::write(&file_path, "newer").unwrap();
+
+        assert!(undo(&state_dir, Some(&run_id)).is_err());
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), "newer");
+    }
+
+    #[test]
+    fn undo_should_skip_files_that_were_not_written() {
+        let dir = TestDir::new("journal-not-written");
+        let state_dir = dir.join("state");
+        let written_path = dir.join("written.txt");
+        let failed_path = dir.join("failed.txt");
+
+        let journal = Journal::create(&state_dir).unwrap();
+        record_change(&journal, &written_path, "old", "new");
+        fs::write(&failed_path, "old").unwrap();
+        journal.record(&failed_path, b"old", b"new").unwrap();
+        let run_id = journal.finish().unwrap().unwrap();
+
+        let undone_run = undo(&state_dir, Some(&run_id)).unwrap();
+        assert_eq!(
+            undone_run.restored_paths,
+            vec![fs::canonicalize(&written_path).unwrap()]
+        );
+        assert_eq!(fs::read_to_string(&written_path).unwrap(), "old");
+        assert_eq!(fs::read_to_string(&failed_path).unwrap(), "old");
+    }
+
+    #[test]
+    fn undo_should_move_renamed_paths_back_before_restoring_them() {
+        let dir = TestDir::new("journal-renames");
+        let state_dir = dir.join("state");
+        let user_dir = dir.join("user");
+        fs::create_dir_all(&user_dir).unwrap();
+        let file_path = user_dir.join("user_service.rs");
+        let renames = vec![
+            PathRename {
+                from: file_path.clone(),
+                to: user_dir.join("account_service.rs"),
+            },
+            PathRename {
+                from: user_dir.clone(),
+                to: dir.join("account"),
+            },
+        ];
+
+        let journal = Journal::create(&state_dir).unwrap();
+        record_change(&journal, &file_path, "user", "account");
+        journal.record_renames(&renames).unwrap();
+        path_renames::execute_renames(&renames).unwrap();
+        let run_id = journal.finish().unwrap().unwrap();
+        assert!(!file_path.exists());
+
+        let undone_run = undo(&state_dir, Some(&run_id)).unwrap();
+        assert_eq!(undone_run.renames.len(), 2);
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), "user");
+        assert!(!dir.join("account").exists());
+    }
+
+    #[test]
+    fn undo_should_not_move_renamed_paths_back_when_a_file_was_modified() {
+        let dir = TestDir::new("journal-renames-modified");
+        let state_dir = dir.join("state");
+        let user_dir = dir.join("user");
+        fs::create_dir_all(&user_dir).unwrap();
+        let file_path = user_dir.join("user_service.rs");
+        let renames = vec![
+            PathRename {
+                from: file_path.clone(),
+                to: user_dir.join("account_service.rs"),
+            },
+            PathRename {
+                from: user_dir.clone(),
+                to: dir.join("account"),
+            },
+        ];
+
+        let journal = Journal::create(&state_dir).unwrap();
+        record_change(&journal, &file_path, "user", "account");
+        journal.record_renames(&renames).unwrap();
+        path_renames::execute_renames(&renames).unwrap();
+        let run_id = journal.finish().unwrap().unwrap();
+        let renamed_file_path = dir.join("account").join("account_service.rs");
+        fs::write(&renamed_file_path, "edited").unwrap();
+
+        let error = undo(&state_dir, Some(&run_id)).unwrap_err();
+        assert!(error.to_string().contains("refusing to undo run"));
+        assert_eq!(fs::read_to_string(&renamed_file_path).unwrap(), "edited");
+        assert!(!user_dir.exists());
+
+        // once the edit is reverted, the run can be undone
+        fs::write(&renamed_file_path, "account").unwrap();
+        undo(&state_dir, Some(&run_id)).unwrap();
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), "user");
+    }
+
+    #[test]
+    fn runs_without_changes_should_not_be_kept() {
+        let dir = TestDir::new("journal-empty");
+        let state_dir = dir.join("state");
+
+        let journal = Journal::create(&state_dir).unwrap();
+        assert_eq!(journal.finish().unwrap(), None);
+        assert!(undo(&state_dir, None).is_err());
+    }
+}
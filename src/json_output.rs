@@ -0,0 +1,159 @@
+use super::file_changes::{ContextLines, Matcher};
+use super::file_io::FileData;
+use super::path_renames::PathRename;
+use serde_json::{json, Value};
+
+/// Builds the record of every match in a file, with 1-based line and column
+/// numbers (the column counts characters, not bytes) and the lines around it.
+///
+/// A multi-line match has the position of its start, and its context is taken
+/// around all the lines it spans. Matches found by a set of rules also have the
+/// label of their rule.
+pub fn file_record(
+    file_data: &FileData,
+    changes_requested: &dyn Matcher,
+    context: ContextLines,
+) -> Value {
+    let contents = &file_data.contents;
+    let matches = file_data
+        .term_spans
+        .iter()
+        .flat_map(|&(first, last)| {
+            let text = file_data.span_text(first, last, changes_requested.multiline());
+            let context_before = &contents[first.saturating_sub(context.before)..first];
+            let context_after = &contents[last + 1..(last + 1 + context.after).min(contents.len())];
+            changes_requested
+                .match_spans(&text, first)
+                .into_iter()
+                .map(|span| {
+                    let mut record = json!({
+                        "line": span.line + 1,
+                        "column": span.column + 1,
+                        "matched": &text[span.start..span.end()],
+                        "replacement": span.replacement,
+                        "context_before": context_before,
+                        "context_after": context_after,
+                    });
+                    if let Some(rule) = span.rule {
+                        record["rule"] = json!(rule);
+                    }
+                    record
+                })
+                .collect::<Vec<Value>>()
+        })
+        .collect::<Vec<Value>>();
+
+    json!({
+        "type": "file",
+        "path": file_data.file_path.to_string_lossy(),
+        "matches": matches,
+    })
+}
+
+pub fn rename_record(rename: &PathRename) -> Value {
+    json!({
+        "type": "rename",
+        "from": rename.from.to_string_lossy(),
+        "to": rename.to.to_string_lossy(),
+    })
+}
+
+pub fn summary_record(files_seen: &usize, files_changed: &usize, run_id: Option<&str>) -> Value {
+    json!({
+        "type": "summary",
+        "files_seen": files_seen,
+        "files_changed": files_changed,
+        "run_id": run_id,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::file_changes::{MatchOptions, WantedChanges};
+    use std::path::Path;
+
+    #[test]
+    fn file_record_should_have_positions_and_context_of_every_match() {
+        let changes_requested = WantedChanges::new(
+            r"(\w+)_id".to_string(),
+            "${1}Id".to_string(),
+            MatchOptions {
+                regex: true,
+                ..MatchOptions::default()
+            },
+        )
+        .unwrap();
+        let file_data = FileData::from_text(
+            Path::new("./mock.rs"),
+            "fn a() {\n  é(user_id, group_id)\n}\n",
+            &changes_requested,
+        )
+        .unwrap();
+
+        let record = file_record(
+            &file_data,
+            &changes_requested,
+            ContextLines {
+                before: 1,
+                after: 2,
+            },
+        );
+
+        assert_eq!(record["type"], "file");
+        assert_eq!(record["path"], "./mock.rs");
+        let matches = record["matches"].as_array().unwrap();
+        assert_eq!(matches.len(), 2);
+        assert_eq!(
+            matches[1],
+            json!({
+                "line": 2,
+                "column": 14,
+                "matched": "group_id",
+                "replacement": "groupId",
+                "context_before": ["fn a() {"],
+                "context_after": ["}"],
+            })
+        );
+    }
+
+    #[test]
+    fn multi_line_matches_should_have_the_position_of_their_start() {
+        let changes_requested = WantedChanges::new(
+            "b\nc".to_string(),
+            "bc".to_string(),
+            MatchOptions {
+                multiline: true,
+                ..MatchOptions::default()
+            },
+        )
+        .unwrap();
+        let file_data =
+            FileData::from_text(Path::new("mock.txt"), "a\nab\nc\nd\n", &changes_requested)
+                .unwrap();
+
+        let record = file_record(&file_data, &changes_requested, ContextLines::default());
+
+        let matches = record["matches"].as_array().unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0]["line"], 2);
+        assert_eq!(matches[0]["column"], 2);
+        assert_eq!(matches[0]["context_before"], json!(["a"]));
+        assert_eq!(matches[0]["context_after"], json!(["d"]));
+    }
+
+    #[test]
+    fn summary_record_should_match_the_counters() {
+        let record = summary_record(&3, &1, None);
+
+        assert_eq!(
+            record,
+            json!({
+                "type": "summary",
+                "files_seen": 3,
+                "files_changed": 1,
+                "run_id": null,
+            })
+        );
+    }
+}
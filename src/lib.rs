@@ -0,0 +1,39 @@
+//! The search and replace engine behind the `turbogrep` CLI.
+//!
+//! The library does not print anything or depend on `clap`, so it can be
+//! embedded in other tools:
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use turbogrep::dir_walker::{self, WalkerOptions};
+//! use turbogrep::file_changes::{ContextLines, FileChanges, MatchOptions, WantedChanges};
+//! use turbogrep::file_io::{self, HardLinkPolicy};
+//!
+//! let changes_requested = WantedChanges::new("old".to_string(), "new".to_string(), MatchOptions::default()).unwrap();
+//! let paths = dir_walker::get_file_paths_that_match_expr(
+//!     &["*.rs", "!**/generated/**"],
+//!     &[Path::new("src"), Path::new("tests")],
+//!     &WalkerOptions::default(),
+//! )?;
+//! for path in paths {
+//!     if let Some(file_data) = file_io::read_file_data_and_check_for_match(&path, &changes_requested, false)? {
+//!         let changes = FileChanges::from_file_data(&file_data, &changes_requested, ContextLines::default());
+//!         file_io::execute_changes_to_file(file_data, changes, None, HardLinkPolicy::default())?;
+//!     }
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+pub mod dir_walker;
+pub mod file_changes;
+pub mod file_io;
+pub mod journal;
+pub mod json_output;
+pub mod path_renames;
+pub mod preserve_case;
+pub mod preview;
+#[cfg(feature = "rules")]
+pub mod rules;
+pub mod streaming;
+#[cfg(test)]
+mod test_dir;
+pub mod unified_diff;
@@ -1,18 +1,36 @@
-use clap::App;
+use clap::{App, AppSettings, SubCommand};
 mod commands;
 mod common;
 mod console_printer;
-mod dir_walker;
-mod file_changes;
-pub use commands::{ClapArg, UserInput};
-mod file_io;
+mod interactive;
+mod runner;
+pub use commands::{ClapArg, UndoInput, UserInput};
+use turbogrep::journal;
 
 fn main() {
     let args = UserInput::get_args();
+    let undo_args = UndoInput::get_args();
     let matches = args
         .into_iter()
-        .fold(App::new("turbogrep"), |acc, arg| acc.arg(arg))
+        .fold(
+            App::new("turbogrep").setting(AppSettings::SubcommandsNegateReqs),
+            |acc, arg| acc.arg(arg),
+        )
+        .subcommand(undo_args.into_iter().fold(
+            SubCommand::with_name("undo").about("reverts the changes made by a previous run"),
+            |acc, arg| acc.arg(arg),
+        ))
         .get_matches();
+
+    if let Some(undo_matches) = matches.subcommand_matches("undo") {
+        let undo_input = UndoInput::from_matches(undo_matches).unwrap();
+        let state_dir = journal::state_dir().unwrap_or_else(|error| common::clap_panic(error));
+        let undone_run = journal::undo(&state_dir, undo_input.run_id.as_deref())
+            .unwrap_or_else(|error| common::clap_panic(error));
+        console_printer::print_undone_run(&undone_run);
+        return;
+    }
+
     let user_input = UserInput::from_matches(&matches).unwrap();
-    file_io::execute(user_input).unwrap();
+    runner::execute(user_input).unwrap_or_else(|error| common::clap_panic(error));
 }
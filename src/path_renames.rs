@@ -0,0 +1,254 @@
+use super::file_changes::WantedChanges;
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A file or directory whose name contains the term, and the path it gets
+/// with the term replaced in its name.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PathRename {
+    pub from: PathBuf,
+    /// Only the last component differs from `from`, the parent directories
+    /// are the ones from before any rename.
+    pub to: PathBuf,
+}
+
+/// Finds the renames for the files and for the directories between the
+/// starting paths and them, sorted with the deepest paths first so that they
+/// can be run in order. The starting paths themselves are never renamed.
+///
+/// Names that the replacement would leave empty are kept. Fails without
+/// renaming anything if a new name is not a file name of its own, if two paths
+/// would get the same name, or if a new path already exists.
+pub fn plan_renames(
+    file_paths: &[PathBuf],
+    starting_paths: &[&Path],
+    changes_requested: &WantedChanges,
+) -> io::Result<Vec<PathRename>> {
+    let mut candidates = BTreeSet::new();
+    file_paths.iter().for_each(|file_path| {
+        let starting_path = starting_paths
+            .iter()
+            .filter(|starting_path| file_path.starts_with(starting_path))
+            .max_by_key(|starting_path| starting_path.components().count());
+        file_path
+            .ancestors()
+            .take_while(|path| Some(*path) != starting_path.map(|path| &**path))
+            .filter(|path| !path.as_os_str().is_empty())
+            .for_each(|path| {
+                candidates.insert(path.to_path_buf());
+            });
+    });
+
+    let new_names = candidates
+        .into_iter()
+        .filter_map(|from| {
+            let name = from.file_name()?.to_str()?;
+            if !changes_requested.is_match(name) {
+                return None;
+            }
+            let new_name = changes_requested.replace_all(name);
+            // a name cannot be replaced by nothing
+            if new_name.is_empty() || new_name == name {
+                return None;
+            }
+            Some((from, new_name))
+        })
+        .collect::<Vec<(PathBuf, String)>>();
+    check_new_names(&new_names)?;
+
+    let mut renames = new_names
+        .into_iter()
+        .map(|(from, new_name)| PathRename {
+            to: from.with_file_name(new_name),
+            from,
+        })
+        .collect::<Vec<PathRename>>();
+    renames.sort_by(|a, b| {
+        let depth = |path: &Path| path.components().count();
+        depth(&b.from)
+            .cmp(&depth(&a.from))
+            .then_with(|| a.from.cmp(&b.from))
+    });
+
+    check_collisions(&renames)?;
+    Ok(renames)
+}
+
+/// A new name with a path separator would move the path to another directory,
+/// and `.` or `..` would not name it at all.
+fn check_new_names(new_names: &[(PathBuf, String)]) -> io::Result<()> {
+    let invalid_names = new_names
+        .iter()
+        .filter(|(_, new_name)| {
+            new_name.contains('/')
+                || new_name.contains(std::path::MAIN_SEPARATOR)
+                || new_name == "."
+                || new_name == ".."
+        })
+        .map(|(from, new_name)| format!("{:?} -> {:?}", from, new_name))
+        .collect::<Vec<String>>();
+    match invalid_names.is_empty() {
+        true => Ok(()),
+        false => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "refusing to rename, these new names are not file names: {}",
+                invalid_names.join(", ")
+            ),
+        )),
+    }
+}
+
+fn check_collisions(renames: &[PathRename]) -> io::Result<()> {
+    let mut new_paths = HashSet::new();
+    let collisions = renames
+        .iter()
+        .filter(|rename| !new_paths.insert(&rename.to) || exists_as_other_file(rename))
+        .map(|rename| format!("{:?} -> {:?}", rename.from, rename.to))
+        .collect::<Vec<String>>();
+    match collisions.is_empty() {
+        true => Ok(()),
+        false => Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!(
+                "refusing to rename, these paths would replace other ones: {}",
+                collisions.join(", ")
+            ),
+        )),
+    }
+}
+
+/// Whether the new path is taken by another file, a rename that only changes
+/// the case on a case-insensitive file system finds the file itself there.
+fn exists_as_other_file(rename: &PathRename) -> bool {
+    match fs::symlink_metadata(&rename.to) {
+        Ok(_) => fs::canonicalize(&rename.to).ok() != fs::canonicalize(&rename.from).ok(),
+        Err(_) => false,
+    }
+}
+
+/// The path the file ends up at once all the renames have run, the same path
+/// if neither the file nor any of its directories is renamed.
+pub fn renamed_path(path: &Path, renames: &[PathRename]) -> PathBuf {
+    let mut original = PathBuf::new();
+    let mut renamed = PathBuf::new();
+    path.components().for_each(|component| {
+        original.push(component);
+        match renames
+            .iter()
+            .find(|rename| rename.from == original)
+            .and_then(|rename| rename.to.file_name())
+        {
+            Some(new_name) => renamed.push(new_name),
+            None => renamed.push(component),
+        }
+    });
+    renamed
+}
+
+/// Runs the renames in order, failing on the first new path that already
+/// exists instead of replacing it.
+pub fn execute_renames(renames: &[PathRename]) -> io::Result<()> {
+    for rename in renames {
+        if exists_as_other_file(rename) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!(
+                    "refusing to rename {:?}, {:?} already exists",
+                    rename.from, rename.to
+                ),
+            ));
+        }
+        fs::rename(&rename.from, &rename.to)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::file_changes::MatchOptions;
+    use crate::test_dir::{mock_wanted_changes, TestDir};
+
+    #[test]
+    fn renames_should_run_deepest_first_and_skip_the_starting_path() {
+        let root = TestDir::new("renames-deepest");
+        let user_dir = root.join("user");
+        fs::create_dir_all(&user_dir).unwrap();
+        let file_paths = vec![user_dir.join("user_service.rs"), root.join("main.rs")];
+        file_paths
+            .iter()
+            .for_each(|path| fs::write(path, "").unwrap());
+
+        let renames = plan_renames(
+            &file_paths,
+            &[&root],
+            &mock_wanted_changes("user", "account"),
+        )
+        .unwrap();
+        assert_eq!(
+            renames,
+            vec![
+                PathRename {
+                    from: user_dir.join("user_service.rs"),
+                    to: user_dir.join("account_service.rs"),
+                },
+                PathRename {
+                    from: user_dir.clone(),
+                    to: root.join("account"),
+                },
+            ]
+        );
+
+        assert_eq!(
+            renamed_path(&file_paths[0], &renames),
+            root.join("account").join("account_service.rs")
+        );
+        assert_eq!(renamed_path(&file_paths[1], &renames), file_paths[1]);
+
+        execute_renames(&renames).unwrap();
+        assert!(root.join("account").join("account_service.rs").exists());
+        assert!(!user_dir.exists());
+    }
+
+    #[test]
+    fn new_names_that_are_not_file_names_should_be_refused() {
+        let root = TestDir::new("renames-invalid-names");
+        let file_paths = vec![root.join("user.rs")];
+        fs::write(&file_paths[0], "").unwrap();
+
+        for new in &["a/b", ".", ".."] {
+            let result = plan_renames(&file_paths, &[&root], &mock_wanted_changes("user.rs", new));
+            assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        }
+        let result = plan_renames(&file_paths, &[&root], &mock_wanted_changes("user", "a/b"));
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn colliding_renames_should_be_refused() {
+        let root = TestDir::new("renames-collisions");
+        let file_paths = vec![root.join("a_old.rs"), root.join("a_new.rs")];
+        file_paths
+            .iter()
+            .for_each(|path| fs::write(path, "").unwrap());
+
+        let result = plan_renames(&file_paths, &[&root], &mock_wanted_changes("old", "new"));
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+
+        let file_paths = vec![root.join("x_1.rs"), root.join("x_2.rs")];
+        let changes_requested = WantedChanges::new(
+            "[12]".to_string(),
+            "n".to_string(),
+            MatchOptions {
+                regex: true,
+                ..MatchOptions::default()
+            },
+        )
+        .unwrap();
+        let result = plan_renames(&file_paths, &[&root], &changes_requested);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+    }
+}
@@ -0,0 +1,145 @@
+use std::fmt;
+
+/// The ways the words of an identifier can be cased and separated.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum CaseVariant {
+    /// `foo_bar`
+    Snake,
+    /// `foo-bar`
+    Kebab,
+    /// `fooBar`
+    Camel,
+    /// `FooBar`
+    Pascal,
+    /// `FOO_BAR`
+    ScreamingSnake,
+}
+
+impl CaseVariant {
+    pub const ALL: [Self; 5] = [
+        Self::Snake,
+        Self::Kebab,
+        Self::Camel,
+        Self::Pascal,
+        Self::ScreamingSnake,
+    ];
+
+    /// Joins the lowercase words in this variant.
+    pub fn apply(&self, words: &[String]) -> String {
+        match self {
+            Self::Snake => words.join("_"),
+            Self::Kebab => words.join("-"),
+            Self::Camel => words
+                .iter()
+                .enumerate()
+                .map(|(index, word)| match index {
+                    0 => word.to_string(),
+                    _ => capitalize(word),
+                })
+                .collect(),
+            Self::Pascal => words.iter().map(|word| capitalize(word)).collect(),
+            Self::ScreamingSnake => words.join("_").to_uppercase(),
+        }
+    }
+}
+
+impl fmt::Display for CaseVariant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Self::Snake => "snake_case",
+            Self::Kebab => "kebab-case",
+            Self::Camel => "camelCase",
+            Self::Pascal => "PascalCase",
+            Self::ScreamingSnake => "SCREAMING_SNAKE_CASE",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Splits the term into lowercase words, on `_`, `-` and whitespace as well
+/// as on case changes (`HTTPServer` gives `http` and `server`).
+pub fn split_words(term: &str) -> Vec<String> {
+    let mut words = vec![];
+    term.split(|c: char| c == '_' || c == '-' || c.is_whitespace())
+        .filter(|chunk| !chunk.is_empty())
+        .for_each(|chunk| {
+            let chars = chunk.chars().collect::<Vec<char>>();
+            let mut word = String::new();
+            for (index, &c) in chars.iter().enumerate() {
+                let prev = index.checked_sub(1).map(|prev| chars[prev]);
+                let next = chars.get(index + 1);
+                let starts_word = c.is_uppercase()
+                    && match prev {
+                        None => false,
+                        Some(prev) if prev.is_uppercase() => next.is_some_and(|c| c.is_lowercase()),
+                        Some(_) => true,
+                    };
+                if starts_word && !word.is_empty() {
+                    words.push(word.to_lowercase());
+                    word.clear();
+                }
+                word.push(c);
+            }
+            words.push(word.to_lowercase());
+        });
+    words
+}
+
+/// Every distinct spelling of the term, with the variant it was spelled in.
+/// A spelling shared by several variants (e.g. `foo` for a single word) is
+/// only listed with the first of them.
+pub fn variants_of(words: &[String]) -> Vec<(String, CaseVariant)> {
+    let mut variants: Vec<(String, CaseVariant)> = vec![];
+    CaseVariant::ALL.iter().for_each(|variant| {
+        let spelling = variant.apply(words);
+        if !variants.iter().any(|(known, _)| *known == spelling) {
+            variants.push((spelling, *variant));
+        }
+    });
+    variants
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn terms_should_be_split_on_separators_and_case_changes() {
+        assert_eq!(split_words("foo_bar"), vec!["foo", "bar"]);
+        assert_eq!(split_words("FOO-BAR"), vec!["foo", "bar"]);
+        assert_eq!(split_words("fooBar"), vec!["foo", "bar"]);
+        assert_eq!(split_words("HTTPServer2"), vec!["http", "server2"]);
+        assert_eq!(split_words("foo"), vec!["foo"]);
+    }
+
+    #[test]
+    fn every_variant_should_be_spelled_once() {
+        let words = split_words("foo_bar");
+        let spellings = variants_of(&words)
+            .into_iter()
+            .map(|(spelling, _)| spelling)
+            .collect::<Vec<String>>();
+        assert_eq!(
+            spellings,
+            vec!["foo_bar", "foo-bar", "fooBar", "FooBar", "FOO_BAR"]
+        );
+
+        let single_word = variants_of(&split_words("foo"));
+        assert_eq!(
+            single_word,
+            vec![
+                ("foo".to_string(), CaseVariant::Snake),
+                ("Foo".to_string(), CaseVariant::Pascal),
+                ("FOO".to_string(), CaseVariant::ScreamingSnake),
+            ]
+        );
+    }
+}
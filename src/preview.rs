@@ -0,0 +1,217 @@
+use super::file_changes::{FileChanges, Hunk, MatchSpan, ParsedLine};
+use super::path_renames::PathRename;
+use ansi_term::{Color, Style};
+
+/// Renders the changes as the colored `old -> new` listing of every line,
+/// with `--` between the hunks.
+pub fn render_changes(changes: &FileChanges) -> String {
+    let hunks = changes
+        .hunks
+        .iter()
+        .map(render_hunk)
+        .collect::<Vec<String>>();
+    format!("{}\n", hunks.join("\n--\n"))
+}
+
+pub fn render_hunk(hunk: &Hunk) -> String {
+    hunk.lines
+        .iter()
+        .map(render_line)
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Renders the line with its matches in red and their replacements in green,
+/// labelled with the rule and case variant they come from, if any.
+/// A line spanning several lines of the file lists the old lines with their
+/// numbers, followed by the new ones.
+pub fn render_line(line: &ParsedLine) -> String {
+    if let Some(line) = without_final_newline(line) {
+        return render_line(&line);
+    }
+    let old = paint_spans(&line.text, &line.spans, |matched, _| {
+        Color::Red.paint(matched).to_string()
+    });
+    if !line.has_term() {
+        return format!("{:>3}|  {}", line.num, old);
+    }
+
+    let new = paint_spans(&line.text, &line.spans, |_, span| {
+        let replacement = Color::Green.paint(&span.replacement).to_string();
+        let labels = span
+            .rule
+            .iter()
+            .map(|rule| format!("[{}]", rule))
+            .chain(span.case_variant.map(|variant| format!("[{}]", variant)))
+            .collect::<String>();
+        match labels.is_empty() {
+            true => replacement,
+            false => format!("{}{}", replacement, Style::new().dimmed().paint(labels)),
+        }
+    });
+    if !old.contains('\n') && !new.contains('\n') {
+        return format!("{:>3}|  {} -> {}", line.num, old, new);
+    }
+
+    let mut display_lines = old
+        .split('\n')
+        .enumerate()
+        .map(|(index, old_line)| format!("{:>3}|  {}", line.num + index, old_line))
+        .collect::<Vec<String>>();
+    display_lines.extend(
+        new.split('\n')
+            .map(|new_line| format!(" ->|  {}", new_line)),
+    );
+    display_lines.join("\n")
+}
+
+/// The line without the final newline of the file that a multi-line match on
+/// the last line can take, which is not shown as a line of its own, nor is it
+/// at the end of the replacements that keep it.
+fn without_final_newline(line: &ParsedLine) -> Option<ParsedLine> {
+    let text = line.text.strip_suffix('\n')?;
+    let spans = line
+        .spans
+        .iter()
+        .map(|span| {
+            let mut span = span.clone();
+            if span.len > 0 && span.end() > text.len() {
+                if let Some(replacement) = span.replacement.strip_suffix('\n') {
+                    span.replacement = replacement.to_string();
+                }
+            }
+            span.start = span.start.min(text.len());
+            span.len = span.len.min(text.len() - span.start);
+            span
+        })
+        .collect();
+    Some(ParsedLine {
+        num: line.num,
+        line_count: line.line_count,
+        text: text.to_string(),
+        spans,
+    })
+}
+
+/// Renders the rename with the old path in red and the new one in green.
+pub fn render_rename(rename: &PathRename) -> String {
+    format!(
+        "{} -> {}",
+        Color::Red.paint(rename.from.to_string_lossy()),
+        Color::Green.paint(rename.to.to_string_lossy())
+    )
+}
+
+fn paint_spans<F>(text: &str, spans: &[MatchSpan], paint: F) -> String
+where
+    F: Fn(&str, &MatchSpan) -> String,
+{
+    let mut painted = String::with_capacity(text.len());
+    let mut last_end = 0;
+    spans.iter().for_each(|span| {
+        painted.push_str(&text[last_end..span.start]);
+        painted.push_str(&paint(&text[span.start..span.end()], span));
+        last_end = span.end();
+    });
+    painted.push_str(&text[last_end..]);
+    painted
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::file_changes::{MatchOptions, WantedChanges};
+    use crate::test_dir::mock_wanted_changes;
+
+    fn parsed_line(text: &str, changes_requested: &WantedChanges) -> ParsedLine {
+        ParsedLine {
+            num: 4,
+            line_count: text.matches('\n').count() + 1,
+            text: text.to_string(),
+            spans: changes_requested.match_spans(text, 4),
+        }
+    }
+
+    #[test]
+    fn changed_lines_should_show_old_and_new_text() {
+        let changes_requested = mock_wanted_changes("a", "b");
+
+        assert_eq!(
+            render_line(&parsed_line("xax", &changes_requested)),
+            format!(
+                "  4|  x{}x -> x{}x",
+                Color::Red.paint("a"),
+                Color::Green.paint("b")
+            )
+        );
+        assert_eq!(
+            render_line(&parsed_line("xyz", &changes_requested)),
+            "  4|  xyz"
+        );
+    }
+
+    #[test]
+    fn multi_line_changes_should_list_old_lines_then_new_ones() {
+        let changes_requested = WantedChanges::new(
+            "a\nb".to_string(),
+            "c".to_string(),
+            MatchOptions {
+                multiline: true,
+                ..MatchOptions::default()
+            },
+        )
+        .unwrap();
+
+        let rendered = render_line(&parsed_line("a\nb", &changes_requested));
+        assert_eq!(rendered.lines().count(), 3);
+        assert!(rendered.starts_with("  4|  "));
+        assert!(rendered.lines().nth(1).unwrap().starts_with("  5|  "));
+        assert!(rendered.lines().nth(2).unwrap().starts_with(" ->|  "));
+    }
+
+    #[test]
+    fn case_variants_should_be_labelled() {
+        let changes_requested = WantedChanges::new(
+            "foo_bar".to_string(),
+            "baz_qux".to_string(),
+            MatchOptions {
+                preserve_case: true,
+                ..MatchOptions::default()
+            },
+        )
+        .unwrap();
+
+        let rendered = render_line(&parsed_line("FooBar", &changes_requested));
+        assert!(rendered.contains("[PascalCase]"));
+    }
+
+    #[test]
+    fn rules_should_be_labelled() {
+        let changes_requested = mock_wanted_changes("a", "b");
+        let mut line = parsed_line("xax", &changes_requested);
+        line.spans[0].rule = Some("a to b".to_string());
+
+        assert!(render_line(&line).contains(&format!(
+            "{}{}",
+            Color::Green.paint("b"),
+            Style::new().dimmed().paint("[a to b]")
+        )));
+    }
+
+    #[test]
+    fn renames_should_show_old_and_new_path() {
+        let rename = PathRename {
+            from: "src/user.rs".into(),
+            to: "src/account.rs".into(),
+        };
+
+        assert_eq!(
+            render_rename(&rename),
+            format!(
+                "{} -> {}",
+                Color::Red.paint("src/user.rs"),
+                Color::Green.paint("src/account.rs")
+            )
+        );
+    }
+}
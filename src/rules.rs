@@ -0,0 +1,385 @@
+use super::dir_walker::PathFilter;
+use super::file_changes::{
+    CaseSensitivity, MatchOptions, MatchSpan, Matcher, WantedChanges, WordChars,
+};
+use serde::Deserialize;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// A rule as written in a rules file, with the same flags as the command
+/// line, e.g. in TOML:
+///
+/// ```toml
+/// [[rules]]
+/// description = "rename the user ids"
+/// glob = "*.rs"
+/// old = "user_id"
+/// new = "account_id"
+/// word = true
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleDefinition {
+    description: Option<String>,
+    glob: Option<String>,
+    old: String,
+    new: String,
+    #[serde(default)]
+    regex: bool,
+    #[serde(default)]
+    ignore_case: bool,
+    #[serde(default)]
+    smart_case: bool,
+    #[serde(default)]
+    word: bool,
+    identifier: Option<String>,
+    word_chars: Option<String>,
+    #[serde(default)]
+    preserve_case: bool,
+    #[serde(default)]
+    multiline: bool,
+}
+
+impl RuleDefinition {
+    fn match_options(&self) -> Result<MatchOptions, String> {
+        let case = match (self.ignore_case, self.smart_case) {
+            (true, _) => CaseSensitivity::Insensitive,
+            (false, true) => CaseSensitivity::Smart,
+            (false, false) => CaseSensitivity::Sensitive,
+        };
+        // like on the command line, `identifier` and `word_chars` imply `word`
+        let word = match (self.word, &self.identifier, &self.word_chars) {
+            (false, None, None) => None,
+            (_, identifier, extra) => {
+                let mut word_chars = match identifier {
+                    None => WordChars::default(),
+                    Some(language) => WordChars::for_language(language)
+                        .ok_or_else(|| format!("unknown language \"{}\"", language))?,
+                };
+                if let Some(extra) = extra {
+                    word_chars.extra.push_str(extra);
+                }
+                Some(word_chars)
+            }
+        };
+        Ok(MatchOptions {
+            regex: self.regex,
+            case,
+            word,
+            preserve_case: self.preserve_case,
+            multiline: self.multiline,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    rules: Vec<RuleDefinition>,
+}
+
+pub struct Rule {
+    /// Shown next to the changes of the rule, its description or its
+    /// position in the rules file. `None` for a term given on its own.
+    pub label: Option<String>,
+    /// The glob of the files the rule applies to, all the files searched if
+    /// `None`.
+    pub glob: Option<String>,
+    filter: Option<PathFilter>,
+    pub changes: WantedChanges,
+}
+
+impl Rule {
+    pub fn applies_to(&self, file_path: &Path, starting_paths: &[&Path]) -> bool {
+        match &self.filter {
+            Some(filter) => filter.matches(file_path, starting_paths),
+            None => true,
+        }
+    }
+}
+
+/// Many replacements applied in a single walk of the files, each to the
+/// files matching its own glob.
+pub struct RuleSet {
+    pub rules: Vec<Rule>,
+}
+
+impl From<WantedChanges> for RuleSet {
+    fn from(changes: WantedChanges) -> Self {
+        Self {
+            rules: vec![Rule {
+                label: None,
+                glob: None,
+                filter: None,
+                changes,
+            }],
+        }
+    }
+}
+
+impl RuleSet {
+    /// Reads the rules from a YAML file if its extension is `.yaml` or
+    /// `.yml`, from a TOML file otherwise.
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path).map_err(|error| {
+            io::Error::new(
+                error.kind(),
+                format!("cannot read rules file {:?}: {}", path, error),
+            )
+        })?;
+        match path.extension().and_then(OsStr::to_str) {
+            Some("yaml") | Some("yml") => Self::from_yaml(&text),
+            _ => Self::from_toml(&text),
+        }
+    }
+
+    pub fn from_toml(text: &str) -> io::Result<Self> {
+        let rules_file: RulesFile = toml::from_str(text).map_err(invalid_rules)?;
+        Self::from_definitions(rules_file.rules)
+    }
+
+    pub fn from_yaml(text: &str) -> io::Result<Self> {
+        let rules_file: RulesFile = serde_yaml::from_str(text).map_err(invalid_rules)?;
+        Self::from_definitions(rules_file.rules)
+    }
+
+    fn from_definitions(definitions: Vec<RuleDefinition>) -> io::Result<Self> {
+        let rules = definitions
+            .into_iter()
+            .enumerate()
+            .map(|(index, definition)| {
+                let label = definition
+                    .description
+                    .clone()
+                    .unwrap_or_else(|| format!("rule {}", index + 1));
+                let invalid_rule = |error: &dyn fmt::Display| {
+                    invalid_rules(format!("invalid rule \"{}\": {}", label, error))
+                };
+                let options = definition
+                    .match_options()
+                    .map_err(|error| invalid_rule(&error))?;
+                let changes = WantedChanges::new(definition.old, definition.new, options)
+                    .map_err(|error| invalid_rule(&error))?;
+                let filter = match &definition.glob {
+                    Some(glob) => {
+                        Some(PathFilter::new(&[glob]).map_err(|error| invalid_rule(&error))?)
+                    }
+                    None => None,
+                };
+                Ok(Rule {
+                    label: Some(label),
+                    glob: definition.glob,
+                    filter,
+                    changes,
+                })
+            })
+            .collect::<io::Result<Vec<Rule>>>()?;
+        Ok(Self { rules })
+    }
+
+    /// The globs of the files any of the rules applies to, to walk them all
+    /// at once. A rule without a glob (or with a `!`-prefixed one) applies to
+    /// almost every file, so it makes the walk go through all of them.
+    pub fn walk_globs(&self) -> Vec<&str> {
+        self.rules
+            .iter()
+            .map(|rule| match &rule.glob {
+                Some(glob) if !glob.starts_with('!') => glob.as_str(),
+                _ => "**",
+            })
+            .collect()
+    }
+
+    /// Every rule, for text that does not come from a file (such as standard
+    /// input) and has no path to match their globs against.
+    pub fn all_rules(&self) -> FileRules<'_> {
+        FileRules {
+            rules: self.rules.iter().collect(),
+        }
+    }
+
+    /// The rules that apply to the file.
+    pub fn for_file(&self, file_path: &Path, starting_paths: &[&Path]) -> FileRules<'_> {
+        FileRules {
+            rules: self
+                .rules
+                .iter()
+                .filter(|rule| rule.applies_to(file_path, starting_paths))
+                .collect(),
+        }
+    }
+}
+
+fn invalid_rules<E>(error: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+/// The rules of a set that apply to a file, matched all together.
+pub struct FileRules<'a> {
+    rules: Vec<&'a Rule>,
+}
+
+impl FileRules<'_> {
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+}
+
+impl Matcher for FileRules<'_> {
+    fn multiline(&self) -> bool {
+        self.rules.iter().any(|rule| rule.changes.options.multiline)
+    }
+
+    fn is_match(&self, line: &str) -> bool {
+        self.rules.iter().any(|rule| rule.changes.is_match(line))
+    }
+
+    /// Where the matches of several rules overlap, the one starting first is
+    /// kept, or the one of the first rule if they start at the same place.
+    fn match_spans(&self, text: &str, first_line: usize) -> Vec<MatchSpan> {
+        let mut spans = self
+            .rules
+            .iter()
+            .enumerate()
+            .flat_map(|(index, rule)| {
+                let rule_spans = match rule.changes.options.multiline {
+                    true => rule.changes.match_spans(text, first_line),
+                    false => line_by_line_spans(&rule.changes, text, first_line),
+                };
+                rule_spans.into_iter().map(move |mut span| {
+                    span.rule = rule.label.clone();
+                    (index, span)
+                })
+            })
+            .collect::<Vec<(usize, MatchSpan)>>();
+        spans.sort_by_key(|(index, span)| (span.start, *index));
+
+        let mut last_end = 0;
+        spans
+            .into_iter()
+            .filter(|(_, span)| {
+                let kept = span.start >= last_end;
+                if kept {
+                    last_end = span.end();
+                }
+                kept
+            })
+            .map(|(_, span)| span)
+            .collect()
+    }
+}
+
+/// The matches of a rule that is not multi-line in text that spans several
+/// lines (because another rule is), each of the lines matched on its own. The
+/// final newline of the file, which the text can end with, is not a line.
+fn line_by_line_spans(changes: &WantedChanges, text: &str, first_line: usize) -> Vec<MatchSpan> {
+    let mut spans = vec![];
+    let mut line_start = 0;
+    let lines = text.strip_suffix('\n').unwrap_or(text);
+    for (index, line) in lines.split('\n').enumerate() {
+        spans.extend(
+            changes
+                .match_spans(line, first_line + index)
+                .into_iter()
+                .map(|mut span| {
+                    span.start += line_start;
+                    span
+                }),
+        );
+        line_start += line.len() + 1;
+    }
+    spans
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const RULES_TOML: &str = r#"
+[[rules]]
+description = "rename the user ids"
+glob = "*.rs"
+old = "user_id"
+new = "account_id"
+
+[[rules]]
+old = "(\\w+)_count"
+new = "num_$1"
+regex = true
+"#;
+
+    #[test]
+    fn rules_should_be_read_from_toml() {
+        let rule_set = RuleSet::from_toml(RULES_TOML).unwrap();
+
+        let labels = rule_set
+            .rules
+            .iter()
+            .map(|rule| rule.label.as_deref())
+            .collect::<Vec<Option<&str>>>();
+        assert_eq!(labels, vec![Some("rename the user ids"), Some("rule 2")]);
+        assert_eq!(rule_set.walk_globs(), vec!["*.rs", "**"]);
+        assert!(rule_set.rules[1].changes.options.regex);
+    }
+
+    #[test]
+    fn rules_should_be_read_from_yaml() {
+        let rule_set = RuleSet::from_yaml(
+            "rules:\n  - old: foo\n    new: bar\n    word: true\n    identifier: css\n",
+        )
+        .unwrap();
+
+        assert_eq!(rule_set.rules.len(), 1);
+        assert_eq!(
+            rule_set.rules[0].changes.options.word,
+            Some(WordChars {
+                extra: "-".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_rules_should_be_err() {
+        assert!(RuleSet::from_toml("[[rules]]\nold = \"a\"\nnew = \"b\"\nnope = 1\n").is_err());
+        assert!(RuleSet::from_toml("[[rules]]\nold = \"(\"\nnew = \"b\"\nregex = true\n").is_err());
+        assert!(RuleSet::from_yaml("rules:\n  - old: a\n").is_err());
+    }
+
+    #[test]
+    fn rules_should_only_apply_to_the_files_matching_their_glob() {
+        let rule_set = RuleSet::from_toml(RULES_TOML).unwrap();
+        let starting_paths = [Path::new(".")];
+
+        let rs_rules = rule_set.for_file(Path::new("./src/main.rs"), &starting_paths);
+        let md_rules = rule_set.for_file(Path::new("./README.md"), &starting_paths);
+        assert!(rs_rules.is_match("user_id"));
+        assert!(!md_rules.is_match("user_id"));
+        assert!(md_rules.is_match("user_count"));
+    }
+
+    #[test]
+    fn spans_should_be_labelled_with_their_rule_and_not_overlap() {
+        let rule_set = RuleSet::from_toml(RULES_TOML).unwrap();
+        let file_rules = rule_set.for_file(Path::new("./src/main.rs"), &[Path::new(".")]);
+
+        let spans = file_rules.match_spans("user_id, user_id_count\nitem_count", 0);
+        let found = spans
+            .iter()
+            .map(|span| (span.line, span.replacement.as_str(), span.rule.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            vec![
+                (0, "account_id", Some("rename the user ids")),
+                (0, "account_id", Some("rename the user ids")),
+                (1, "num_item", Some("rule 2")),
+            ]
+        );
+    }
+}
@@ -0,0 +1,372 @@
+use super::commands::{OutputFormat, UserInput};
+use super::{console_printer, interactive};
+use rayon::prelude::*;
+use rayon::ThreadPool;
+use std::collections::HashSet;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use turbogrep::dir_walker;
+use turbogrep::file_changes::{FileChanges, Matcher};
+use turbogrep::file_io::{self, FileData};
+use turbogrep::journal::{self, Journal};
+use turbogrep::path_renames;
+use turbogrep::rules::RuleSet;
+use turbogrep::streaming;
+
+/// The files are read in chunks of this many, printed and written before the
+/// next chunk is read, so that only the contents of one chunk are held in
+/// memory at a time.
+const FILES_PER_CHUNK: usize = 256;
+
+/// A file read in the parallel pass, or one too large to be read whole that is
+/// left to be streamed in the ordered pass instead.
+enum ReadFile<T> {
+    Read(Option<T>),
+    TooLarge,
+}
+
+pub fn execute(user_input: UserInput) -> io::Result<()> {
+    let rule_set = user_input.rule_set()?;
+    if user_input.stdin {
+        return replace_in_stdin(&rule_set);
+    }
+
+    let starting_paths = user_input
+        .paths
+        .iter()
+        .map(Path::new)
+        .collect::<Vec<&Path>>();
+    let path_globs = user_input.path_globs(&rule_set);
+    let path_globs = path_globs.iter().map(String::as_str).collect::<Vec<&str>>();
+    let walker_options = user_input.walker_options();
+    let file_paths =
+        dir_walker::get_file_paths_that_match_expr(&path_globs, &starting_paths, &walker_options)?;
+
+    let context = user_input.context_lines();
+    // collisions are found before anything is written
+    let renames = match user_input.rename_paths {
+        true => {
+            path_renames::plan_renames(&file_paths, &starting_paths, &user_input.wanted_changes())?
+        }
+        false => vec![],
+    };
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(user_input.threads)
+        .build()
+        .map_err(io::Error::other)?;
+    if user_input.stdout {
+        return print_replaced_files(&user_input, &rule_set, &file_paths, &starting_paths, &pool);
+    }
+
+    // the diff, the JSON records and the prompts need every hunk of a file at
+    // once, so only the preview can stream the hunks of large files
+    let can_stream = (user_input.silent || user_input.format == OutputFormat::Preview)
+        && !user_input.interactive;
+
+    // in interactive mode, each file is written as soon as its hunks are
+    // answered, so that the answers are kept if the run is stopped later on
+    let chunk_size = match user_input.interactive {
+        true => 1,
+        false => FILES_PER_CHUNK,
+    };
+    let files_seen = file_paths.len();
+    let mut files_changed = 0;
+    let mut journal = None;
+    let mut quit = false;
+    // the files whose diff already moves them, if they are renamed
+    let mut diffed_paths = HashSet::new();
+
+    for chunk in file_paths.chunks(chunk_size) {
+        // files are read and diffed in parallel, but the results keep the order
+        // of the paths so that the output stays the same between runs
+        let files_with_matches = pool.install(|| {
+            chunk
+                .par_iter()
+                .map(|file_path| {
+                    let file_rules = rule_set.for_file(file_path, &starting_paths);
+                    if file_rules.is_empty() {
+                        return Ok(ReadFile::Read(None));
+                    }
+                    if can_stream && streaming::should_stream(file_path, &file_rules) {
+                        return Ok(ReadFile::TooLarge);
+                    }
+                    let possible_data = file_io::read_file_data_and_check_for_match(
+                        file_path,
+                        &file_rules,
+                        user_input.binary,
+                    )?;
+                    Ok(ReadFile::Read(possible_data.map(|file_data| {
+                        let changes_to_be_made =
+                            FileChanges::from_file_data(&file_data, &file_rules, context);
+                        (file_data, changes_to_be_made)
+                    })))
+                })
+                .collect::<Vec<io::Result<ReadFile<(FileData, FileChanges)>>>>()
+        });
+
+        let mut changes_to_write = vec![];
+        let mut large_files_to_write = vec![];
+        for (file_path, file_result) in chunk.iter().zip(files_with_matches) {
+            // a file that cannot be read should not stop the others from being changed
+            let (file_data, changes_to_be_made) = match file_result {
+                Ok(ReadFile::Read(Some(file_with_matches))) => file_with_matches,
+                Ok(ReadFile::Read(None)) => continue,
+                Ok(ReadFile::TooLarge) => {
+                    let file_rules = rule_set.for_file(file_path, &starting_paths);
+                    match search_large_file(&user_input, file_path, &file_rules) {
+                        Ok(true) if !user_input.dry_run && user_input.replaces() => {
+                            large_files_to_write.push(file_path)
+                        }
+                        Ok(_) => {}
+                        Err(error) => console_printer::print_file_warning(file_path, &error),
+                    }
+                    continue;
+                }
+                Err(error) => {
+                    console_printer::print_file_warning(file_path, &error);
+                    continue;
+                }
+            };
+            if !user_input.silent {
+                match user_input.format {
+                    OutputFormat::Preview => {
+                        console_printer::print_file_path_header_to_console(file_path);
+                        // in interactive mode, each change is shown when asking about it instead
+                        if !user_input.interactive {
+                            console_printer::print_changes_to_be_made(&changes_to_be_made);
+                        }
+                    }
+                    OutputFormat::Diff => {
+                        let renamed_path = path_renames::renamed_path(file_path, &renames);
+                        console_printer::print_unified_diff(
+                            &file_data,
+                            &changes_to_be_made,
+                            &renamed_path,
+                            &starting_paths,
+                        );
+                        diffed_paths.insert(file_path);
+                    }
+                    OutputFormat::Json => console_printer::print_json_file_record(
+                        &file_data,
+                        &rule_set.for_file(file_path, &starting_paths),
+                        context,
+                    ),
+                }
+            }
+
+            if !user_input.dry_run && user_input.replaces() {
+                let changes_to_be_made = match user_input.interactive {
+                    true => {
+                        let selection = interactive::select_changes(file_path, changes_to_be_made)?;
+                        quit = selection.quit;
+                        selection.changes
+                    }
+                    false => changes_to_be_made,
+                };
+
+                if !changes_to_be_made.is_empty() {
+                    changes_to_write.push((file_data, changes_to_be_made));
+                }
+                if quit {
+                    break;
+                }
+            }
+        }
+
+        if !changes_to_write.is_empty() || !large_files_to_write.is_empty() {
+            // the journal is only created once there is something to write
+            if journal.is_none() {
+                journal = Some(Journal::create(&journal::state_dir()?)?);
+            }
+            // a file that cannot be written should not stop the others either,
+            // nor keep the journal of the ones written from being finished
+            let write_results = pool.install(|| {
+                let mut write_results = changes_to_write
+                    .into_par_iter()
+                    .map(|(file_data, changes)| {
+                        let file_path = file_data.file_path.clone();
+                        let result = file_io::execute_changes_to_file(
+                            file_data,
+                            changes,
+                            journal.as_ref(),
+                            user_input.hard_links,
+                        );
+                        (file_path, result)
+                    })
+                    .collect::<Vec<(PathBuf, io::Result<()>)>>();
+                write_results.par_extend(large_files_to_write.into_par_iter().map(|file_path| {
+                    // the lines too long to change were already warned about
+                    // when searching the file
+                    let result = streaming::replace_in_file(
+                        file_path,
+                        &rule_set.for_file(file_path, &starting_paths),
+                        journal.as_ref(),
+                        user_input.hard_links,
+                    )
+                    .map(|_| ());
+                    (file_path.clone(), result)
+                }));
+                write_results
+            });
+            for (file_path, result) in write_results {
+                match result {
+                    Ok(()) => files_changed += 1,
+                    Err(error) => console_printer::print_file_warning(&file_path, &error),
+                }
+            }
+        }
+        if quit {
+            break;
+        }
+    }
+
+    if !user_input.silent && !renames.is_empty() {
+        let undiffed_paths = file_paths
+            .iter()
+            .filter(|file_path| !diffed_paths.contains(file_path))
+            .cloned()
+            .collect::<Vec<PathBuf>>();
+        console_printer::print_renames(
+            &renames,
+            &undiffed_paths,
+            &starting_paths,
+            &user_input.format,
+        );
+    }
+
+    // the contents are written first, since the renames move the files
+    let renames_to_run = !user_input.dry_run && user_input.replaces();
+    let mut renames_result = Ok(());
+    if renames_to_run && !renames.is_empty() {
+        let run_journal = match journal.take() {
+            Some(run_journal) => run_journal,
+            None => Journal::create(&journal::state_dir()?)?,
+        };
+        renames_result = run_journal
+            .record_renames(&renames)
+            .and_then(|()| path_renames::execute_renames(&renames));
+        journal = Some(run_journal);
+    }
+    // the journal is finished (and the run id printed) even if a rename
+    // failed, so that the run can still be undone
+    let run_id = match journal {
+        Some(journal) => journal.finish()?,
+        None => None,
+    };
+
+    console_printer::print_summary(
+        &files_seen,
+        &files_changed,
+        run_id.as_deref(),
+        &user_input.format,
+    );
+    let renamed = renames_to_run && !renames.is_empty() && renames_result.is_ok();
+    if renamed && user_input.format != OutputFormat::Json {
+        console_printer::print_renamed_count(&renames.len(), &user_input.format);
+    }
+
+    renames_result
+}
+
+/// Searches a file too large to hold in memory, printing its hunks as they are
+/// found, and returns whether it has any match.
+fn search_large_file(
+    user_input: &UserInput,
+    file_path: &Path,
+    changes_requested: &dyn Matcher,
+) -> io::Result<bool> {
+    let mut hunk_count = 0;
+    let searched_file = streaming::search_file(
+        file_path,
+        changes_requested,
+        user_input.context_lines(),
+        user_input.binary,
+        |hunk| {
+            if !user_input.silent {
+                if hunk_count == 0 {
+                    console_printer::print_file_path_header_to_console(file_path);
+                }
+                console_printer::print_streamed_hunk(&hunk, hunk_count == 0);
+            }
+            hunk_count += 1;
+            Ok(())
+        },
+    )?;
+    if searched_file.matched && !user_input.silent {
+        console_printer::print_end_of_streamed_hunks();
+    }
+    if let Some(long_lines) = searched_file.long_lines {
+        console_printer::print_long_lines_warning(file_path, &long_lines);
+    }
+    Ok(searched_file.matched)
+}
+
+/// Prints the standard input with the term replaced, like `sed` does.
+fn replace_in_stdin(rule_set: &RuleSet) -> io::Result<()> {
+    let file_rules = rule_set.all_rules();
+    // without multi-line rules, the input can be replaced as it comes
+    if !file_rules.multiline() {
+        return console_printer::print_replaced_lines(io::stdin().lock(), &file_rules);
+    }
+
+    let mut contents = vec![];
+    io::stdin().lock().read_to_end(&mut contents)?;
+    let text = file_io::text_from_utf8(contents)?;
+    console_printer::print_replaced_text(&file_io::replace_in_text(
+        Path::new("-"),
+        &text,
+        &file_rules,
+    ))
+}
+
+/// Prints every file with the term replaced, in the order of the paths,
+/// instead of writing them. Files without matches are printed as they are.
+fn print_replaced_files(
+    user_input: &UserInput,
+    rule_set: &RuleSet,
+    file_paths: &[PathBuf],
+    starting_paths: &[&Path],
+    pool: &ThreadPool,
+) -> io::Result<()> {
+    for chunk in file_paths.chunks(FILES_PER_CHUNK) {
+        let replaced_texts = pool.install(|| {
+            chunk
+                .par_iter()
+                .map(|file_path| {
+                    let file_rules = rule_set.for_file(file_path, starting_paths);
+                    if streaming::should_stream(file_path, &file_rules) {
+                        return Ok(ReadFile::TooLarge);
+                    }
+                    let text = file_io::read_file_text(file_path, user_input.binary)?;
+                    Ok(ReadFile::Read(text.map(|text| {
+                        file_io::replace_in_text(file_path, &text, &file_rules)
+                    })))
+                })
+                .collect::<Vec<io::Result<ReadFile<String>>>>()
+        });
+
+        for (file_path, replaced_text) in chunk.iter().zip(replaced_texts) {
+            match replaced_text {
+                Ok(ReadFile::Read(Some(text))) => console_printer::print_replaced_text(&text)?,
+                Ok(ReadFile::Read(None)) => {}
+                Ok(ReadFile::TooLarge) => {
+                    let file_rules = rule_set.for_file(file_path, starting_paths);
+                    match console_printer::print_replaced_file(
+                        file_path,
+                        &file_rules,
+                        user_input.binary,
+                    ) {
+                        Ok(()) => {}
+                        // a closed pipe ends the run, like it does for the other files
+                        Err(error) if error.kind() == io::ErrorKind::BrokenPipe => {
+                            return Err(error)
+                        }
+                        Err(error) => console_printer::print_file_warning(file_path, &error),
+                    }
+                }
+                Err(error) => console_printer::print_file_warning(file_path, &error),
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,563 @@
+use super::file_changes::{ContextLines, Hunk, Matcher, ParsedLine};
+use super::file_io::{self, HardLinkPolicy, LineEnding, ReplacementFile, UTF8_BOM};
+use super::journal::{ContentHasher, Journal};
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::mem;
+use std::path::Path;
+
+/// Files at least this large are searched and rewritten line by line instead
+/// of being read whole into memory.
+pub const STREAMING_THRESHOLD: u64 = 64 * 1024 * 1024;
+
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// Lines longer than this, with their line ending, are not read into memory:
+/// they are neither searched nor changed, see `LongLines`.
+pub const MAX_LINE_LEN: usize = 1024 * 1024;
+
+/// A hunk is handed over once it has this many lines, even if the lines after
+/// it belong to it, so that a file matching on every line is not held in
+/// memory all the same.
+const MAX_HUNK_LINES: usize = 1000;
+
+/// Whether the file is large enough to be streamed. Multi-line matches can
+/// span any number of lines, so only a matcher that matches line by line can
+/// stream.
+pub fn should_stream(file_path: &Path, changes_requested: &dyn Matcher) -> bool {
+    !changes_requested.multiline()
+        && fs::metadata(file_path).is_ok_and(|metadata| metadata.len() >= STREAMING_THRESHOLD)
+}
+
+struct Line<'a> {
+    num: usize,
+    /// The text of the line, without its line ending or byte order mark.
+    text: &'a str,
+    line_ending: LineEnding,
+    /// Whether the line is the first one and the byte order mark was before it.
+    after_bom: bool,
+}
+
+enum NextLine<'a> {
+    Line(Line<'a>),
+    /// A line longer than `MAX_LINE_LEN`, only the start of which was read,
+    /// the rest has to be passed with `Lines::copy_long_line`.
+    TooLong,
+}
+
+/// The lines of a streamed file longer than `MAX_LINE_LEN`, which were left
+/// as they are instead of being read into memory.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct LongLines {
+    pub count: usize,
+    /// The number of the first of them, from 0 like the other line numbers.
+    pub first: usize,
+}
+
+impl fmt::Display for LongLines {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} line(s) longer than {} bytes, from line {} on, were not searched",
+            self.count,
+            MAX_LINE_LEN,
+            self.first + 1
+        )
+    }
+}
+
+/// Reads the lines one at a time, each into the same buffer.
+struct Lines<R> {
+    reader: R,
+    buffer: Vec<u8>,
+    count: usize,
+    /// The offset of the next line from the start of the file, in bytes.
+    offset: usize,
+    long_lines: Option<LongLines>,
+}
+
+impl<R: BufRead> Lines<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buffer: vec![],
+            count: 0,
+            offset: 0,
+            long_lines: None,
+        }
+    }
+
+    /// Splits the lines the same way as `FileData::from_text`, failing with
+    /// the same `InvalidData` error on text that is not valid UTF-8.
+    fn next_line(&mut self) -> io::Result<Option<NextLine<'_>>> {
+        self.buffer.clear();
+        let ended = read_line_up_to(&mut self.reader, &mut self.buffer, MAX_LINE_LEN)?;
+        let read = self.buffer.len();
+        if read == 0 {
+            return Ok(None);
+        }
+        if !ended {
+            let long_lines = self.long_lines.get_or_insert(LongLines {
+                count: 0,
+                first: self.count,
+            });
+            long_lines.count += 1;
+            self.count += 1;
+            self.offset += read;
+            return Ok(Some(NextLine::TooLong));
+        }
+
+        let line_ending = match self.buffer.strip_suffix(b"\n") {
+            Some(line) => match line.ends_with(b"\r") {
+                true => LineEnding::CrLf,
+                false => LineEnding::Lf,
+            },
+            None => LineEnding::None,
+        };
+        let after_bom = self.count == 0 && self.buffer.starts_with(UTF8_BOM.as_bytes());
+        let start = match after_bom {
+            true => UTF8_BOM.len(),
+            false => 0,
+        };
+        let end = read - line_ending.as_str().len();
+
+        let num = self.count;
+        let line_offset = self.offset;
+        self.count += 1;
+        self.offset += read;
+        let text = std::str::from_utf8(&self.buffer[start..end])
+            .map_err(|error| file_io::invalid_utf8(line_offset + start + error.valid_up_to()))?;
+        Ok(Some(NextLine::Line(Line {
+            num,
+            text,
+            line_ending,
+            after_bom,
+        })))
+    }
+
+    /// Copies the line that was too long to the writer as it is, the start
+    /// already read and then the rest a buffer at a time, up to and including
+    /// its newline.
+    fn copy_long_line<W: Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.buffer)?;
+        loop {
+            self.buffer.clear();
+            let ended = read_line_up_to(&mut self.reader, &mut self.buffer, BUFFER_SIZE)?;
+            self.offset += self.buffer.len();
+            writer.write_all(&self.buffer)?;
+            if ended {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Reads up to and including the next newline, like `read_until`, but no more
+/// than `limit` bytes. Returns whether the line (or the file) ended first.
+fn read_line_up_to<R: BufRead>(
+    reader: &mut R,
+    buffer: &mut Vec<u8>,
+    limit: usize,
+) -> io::Result<bool> {
+    loop {
+        let available = match reader.fill_buf() {
+            Ok(available) => available,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        };
+        if available.is_empty() {
+            return Ok(true);
+        }
+        let available = &available[..available.len().min(limit - buffer.len())];
+        let (used, ended) = match available.iter().position(|&byte| byte == b'\n') {
+            Some(newline) => (newline + 1, true),
+            None => (available.len(), false),
+        };
+        buffer.extend_from_slice(&available[..used]);
+        reader.consume(used);
+        if ended {
+            return Ok(true);
+        }
+        if buffer.len() == limit {
+            return Ok(false);
+        }
+    }
+}
+
+/// Opens the file to be read through a fixed-size buffer, `None` for a binary
+/// file unless `search_binary` is set.
+fn open_text_file(file_path: &Path, search_binary: bool) -> io::Result<Option<BufReader<File>>> {
+    let mut reader = BufReader::with_capacity(BUFFER_SIZE, File::open(file_path)?);
+    if !search_binary && file_io::is_binary(reader.fill_buf()?) {
+        return Ok(None);
+    }
+    Ok(Some(reader))
+}
+
+/// Searches the file line by line, handing each hunk to `on_hunk` as soon as
+/// it is complete. Only the context lines before the current one (and the
+/// open hunk) are kept in memory.
+///
+/// The hunks are the ones `FileChanges::from_file_data` finds, except that a
+/// hunk longer than `MAX_HUNK_LINES` is split, and that a line longer than
+/// `MAX_LINE_LEN` is skipped, ending the hunk before it. Binary files are
+/// skipped (nothing matching) unless `search_binary` is set.
+pub fn search_file<F>(
+    file_path: &Path,
+    changes_requested: &dyn Matcher,
+    context: ContextLines,
+    search_binary: bool,
+    mut on_hunk: F,
+) -> io::Result<SearchedFile>
+where
+    F: FnMut(Hunk) -> io::Result<()>,
+{
+    let reader = match open_text_file(file_path, search_binary)? {
+        Some(reader) => reader,
+        None => {
+            return Ok(SearchedFile {
+                matched: false,
+                long_lines: None,
+            })
+        }
+    };
+    let mut lines = Lines::new(reader);
+    let mut before = VecDeque::with_capacity(context.before);
+    let mut hunk_lines = vec![];
+    let mut after_left = 0;
+    let mut matched = false;
+
+    while let Some(next_line) = lines.next_line()? {
+        let line = match next_line {
+            NextLine::Line(line) => line,
+            NextLine::TooLong => {
+                lines.copy_long_line(&mut io::sink())?;
+                // the line is not kept, so it cannot be a context line either
+                if !hunk_lines.is_empty() {
+                    on_hunk(Hunk {
+                        lines: mem::take(&mut hunk_lines),
+                    })?;
+                }
+                before.clear();
+                after_left = 0;
+                continue;
+            }
+        };
+        let parsed = |spans| ParsedLine {
+            num: line.num,
+            line_count: 1,
+            text: line.text.to_string(),
+            spans,
+        };
+        if changes_requested.is_match(line.text) {
+            matched = true;
+            hunk_lines.extend(before.drain(..));
+            hunk_lines.push(parsed(changes_requested.match_spans(line.text, line.num)));
+            after_left = context.after;
+        } else if after_left > 0 {
+            hunk_lines.push(parsed(vec![]));
+            after_left -= 1;
+        } else {
+            // the gap is too long for the next match to share the open hunk
+            if !hunk_lines.is_empty() && before.len() == context.before {
+                on_hunk(Hunk {
+                    lines: mem::take(&mut hunk_lines),
+                })?;
+            }
+            if context.before > 0 {
+                if before.len() == context.before {
+                    before.pop_front();
+                }
+                before.push_back(parsed(vec![]));
+            }
+        }
+
+        if hunk_lines.len() >= MAX_HUNK_LINES {
+            on_hunk(Hunk {
+                lines: mem::take(&mut hunk_lines),
+            })?;
+        }
+    }
+    if !hunk_lines.is_empty() {
+        on_hunk(Hunk { lines: hunk_lines })?;
+    }
+    Ok(SearchedFile {
+        matched,
+        long_lines: lines.long_lines,
+    })
+}
+
+/// What `search_file` found in the file.
+#[derive(Debug, Eq, PartialEq)]
+pub struct SearchedFile {
+    /// Whether any line matched.
+    pub matched: bool,
+    pub long_lines: Option<LongLines>,
+}
+
+/// Copies the text to the writer line by line, with every match replaced and
+/// the line endings and byte order mark kept, the same as `apply_changes`.
+/// Lines longer than `MAX_LINE_LEN` are copied as they are, and returned.
+pub fn replace_lines<R, W>(
+    reader: R,
+    changes_requested: &dyn Matcher,
+    writer: &mut W,
+) -> io::Result<Option<LongLines>>
+where
+    R: BufRead,
+    W: Write,
+{
+    let mut lines = Lines::new(reader);
+    while let Some(next_line) = lines.next_line()? {
+        let line = match next_line {
+            NextLine::Line(line) => line,
+            NextLine::TooLong => {
+                lines.copy_long_line(writer)?;
+                continue;
+            }
+        };
+        if line.after_bom {
+            writer.write_all(UTF8_BOM.as_bytes())?;
+        }
+        match changes_requested.is_match(line.text) {
+            true => {
+                let new_text = ParsedLine {
+                    num: line.num,
+                    line_count: 1,
+                    text: line.text.to_string(),
+                    spans: changes_requested.match_spans(line.text, line.num),
+                }
+                .new_text();
+                let inner_ending = match line.line_ending {
+                    LineEnding::None => LineEnding::Lf,
+                    line_ending => line_ending,
+                };
+                writer.write_all(new_text.replace('\n', inner_ending.as_str()).as_bytes())?;
+            }
+            false => writer.write_all(line.text.as_bytes())?,
+        }
+        writer.write_all(line.line_ending.as_str().as_bytes())?;
+    }
+    Ok(lines.long_lines)
+}
+
+/// Writes the file with every match replaced to the writer, skipping binary
+/// files unless `search_binary` is set. Returns the lines left as they are,
+/// as `replace_lines` does.
+pub fn write_replaced_file<W: Write>(
+    file_path: &Path,
+    changes_requested: &dyn Matcher,
+    search_binary: bool,
+    writer: &mut W,
+) -> io::Result<Option<LongLines>> {
+    match open_text_file(file_path, search_binary)? {
+        Some(reader) => replace_lines(reader, changes_requested, writer),
+        None => Ok(None),
+    }
+}
+
+/// Replaces every match in the file, streaming the new contents to a
+/// temporary file that then replaces it. The original contents are copied to
+/// the journal, if one is given, just before. Returns the lines left as they
+/// are, as `replace_lines` does.
+pub fn replace_in_file(
+    file_path: &Path,
+    changes_requested: &dyn Matcher,
+    journal: Option<&Journal>,
+    hard_links: HardLinkPolicy,
+) -> io::Result<Option<LongLines>> {
+    let reader = BufReader::with_capacity(BUFFER_SIZE, File::open(file_path)?);
+    let mut writer = HashingWriter {
+        writer: ReplacementFile::create(file_path, hard_links)?,
+        hasher: ContentHasher::default(),
+    };
+    let long_lines = replace_lines(reader, changes_requested, &mut writer)?;
+
+    let HashingWriter {
+        writer: replacement,
+        hasher,
+    } = writer;
+    if let Some(journal) = journal {
+        journal.record_file(file_path, hasher.finish())?;
+    }
+    replacement.commit()?;
+    Ok(long_lines)
+}
+
+/// Hashes everything written through it, for the journal.
+struct HashingWriter<W> {
+    writer: W,
+    hasher: ContentHasher,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.writer.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::file_changes::{FileChanges, WantedChanges};
+    use crate::file_io::FileData;
+    use crate::journal;
+    use crate::test_dir::{mock_wanted_changes, TestDir};
+    use std::io::Cursor;
+
+    fn streamed_hunks(
+        file_path: &Path,
+        changes_requested: &WantedChanges,
+        context: ContextLines,
+    ) -> Vec<Hunk> {
+        let mut hunks = vec![];
+        search_file(file_path, changes_requested, context, false, |hunk| {
+            hunks.push(hunk);
+            Ok(())
+        })
+        .unwrap();
+        hunks
+    }
+
+    #[test]
+    fn streamed_hunks_should_be_the_same_as_in_memory() {
+        let text = "old a\nb\nc\nold d\ne\nf\ng\nh\ni\nold j\r\nk\nold l";
+        let dir = TestDir::new("streaming-hunks");
+        let file_path = dir.file(text.as_bytes());
+        let changes_requested = mock_wanted_changes("old", "new");
+
+        let file_data = FileData::from_text(&file_path, text, &changes_requested).unwrap();
+        for (before, after) in [(0, 0), (1, 0), (0, 1), (2, 2), (3, 3), (10, 10)] {
+            let context = ContextLines { before, after };
+            let in_memory = FileChanges::from_file_data(&file_data, &changes_requested, context);
+            assert_eq!(
+                streamed_hunks(&file_path, &changes_requested, context),
+                in_memory.hunks,
+                "context {:?}",
+                context
+            );
+        }
+    }
+
+    #[test]
+    fn long_hunks_should_be_split() {
+        let text = "old\n".repeat(MAX_HUNK_LINES + 5);
+        let dir = TestDir::new("streaming-long-hunk");
+        let file_path = dir.file(text.as_bytes());
+
+        let hunks = streamed_hunks(
+            &file_path,
+            &mock_wanted_changes("old", "new"),
+            ContextLines::default(),
+        );
+        let lengths = hunks
+            .iter()
+            .map(|hunk| hunk.lines.len())
+            .collect::<Vec<usize>>();
+        assert_eq!(lengths, vec![MAX_HUNK_LINES, 5]);
+        assert_eq!(hunks[1].start(), MAX_HUNK_LINES);
+    }
+
+    #[test]
+    fn invalid_utf8_should_be_err_with_its_offset() {
+        let dir = TestDir::new("streaming-invalid-utf8");
+        let file_path = dir.file(b"old a\nold \xff\n");
+
+        let result = search_file(
+            &file_path,
+            &mock_wanted_changes("old", "new"),
+            ContextLines::default(),
+            false,
+            |_| Ok(()),
+        );
+        let error = result.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("offset 10"));
+    }
+
+    #[test]
+    fn lines_longer_than_the_maximum_should_be_left_as_they_are() {
+        let long_line = "old ".repeat(MAX_LINE_LEN / 4 + 1);
+        let longest_line = "x".repeat(MAX_LINE_LEN - 1);
+        let text = format!("old a\n{}\n{}\nold b", long_line, longest_line);
+        let changes_requested = mock_wanted_changes("old", "new");
+
+        let mut replaced = vec![];
+        let long_lines =
+            replace_lines(Cursor::new(&text), &changes_requested, &mut replaced).unwrap();
+        assert_eq!(long_lines, Some(LongLines { count: 1, first: 1 }));
+        assert_eq!(
+            String::from_utf8(replaced).unwrap(),
+            format!("new a\n{}\n{}\nnew b", long_line, longest_line)
+        );
+
+        let dir = TestDir::new("streaming-long-lines");
+        let file_path = dir.file(text.as_bytes());
+        let mut hunks = vec![];
+        let searched_file = search_file(
+            &file_path,
+            &changes_requested,
+            ContextLines::default(),
+            false,
+            |hunk| {
+                hunks.push(hunk);
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            searched_file.long_lines,
+            Some(LongLines { count: 1, first: 1 })
+        );
+        let line_nums = hunks
+            .iter()
+            .map(|hunk| hunk.lines.iter().map(|line| line.num).collect())
+            .collect::<Vec<Vec<usize>>>();
+        assert_eq!(line_nums, vec![vec![0], vec![2, 3]]);
+    }
+
+    #[test]
+    fn replaced_lines_should_be_the_same_as_in_memory() {
+        let changes_requested = mock_wanted_changes("old", "new\nline");
+        for text in ["\u{feff}old a\r\nb\nold c", "a\nold b\n", ""] {
+            let mut streamed = vec![];
+            replace_lines(Cursor::new(text), &changes_requested, &mut streamed).unwrap();
+            assert_eq!(
+                String::from_utf8(streamed).unwrap(),
+                file_io::replace_in_text(Path::new("mock.txt"), text, &changes_requested)
+            );
+        }
+    }
+
+    #[test]
+    fn replaced_files_should_be_journalled_and_undone() {
+        let dir = TestDir::new("streaming-journalled");
+        let state_dir = dir.join("state");
+        let file_path = dir.file(b"old a\nb\n");
+
+        let run_id = {
+            let journal = Journal::create(&state_dir).unwrap();
+            replace_in_file(
+                &file_path,
+                &mock_wanted_changes("old", "new"),
+                Some(&journal),
+                HardLinkPolicy::default(),
+            )
+            .unwrap();
+            journal.finish().unwrap().unwrap()
+        };
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), "new a\nb\n");
+
+        let undone_run = journal::undo(&state_dir, Some(&run_id)).unwrap();
+        assert_eq!(undone_run.run_id, run_id);
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), "old a\nb\n");
+    }
+}
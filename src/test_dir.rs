@@ -0,0 +1,47 @@
+//! Fixtures shared by the unit tests.
+
+use crate::file_changes::{MatchOptions, WantedChanges};
+use std::env;
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// The plain (literal, case-sensitive) change of `old` into `new`.
+pub(crate) fn mock_wanted_changes(old: &str, new: &str) -> WantedChanges {
+    WantedChanges::new(old.to_string(), new.to_string(), MatchOptions::default()).unwrap()
+}
+
+/// A directory under the system temp dir for the files of a test, removed
+/// with everything in it when dropped, so that a failing assertion does not
+/// leave it behind.
+pub(crate) struct TestDir(PathBuf);
+
+impl TestDir {
+    /// The name has to be unique among the tests, which run in parallel.
+    pub(crate) fn new(name: &str) -> Self {
+        let path = env::temp_dir().join(format!("turbogrep-test-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    /// Writes a file named `file` in the directory, returning its path.
+    pub(crate) fn file(&self, contents: &[u8]) -> PathBuf {
+        let file_path = self.join("file");
+        fs::write(&file_path, contents).unwrap();
+        file_path
+    }
+}
+
+impl Deref for TestDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
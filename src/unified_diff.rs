@@ -0,0 +1,398 @@
+use super::dir_walker;
+use super::file_changes::FileChanges;
+use super::file_io::{FileData, LineEnding, UTF8_BOM};
+use std::path::{Component, Path};
+
+/// Renders the changes as a unified diff that `git apply` or `patch -p1` can
+/// apply to the original file, see `diff_path` for the path it is given.
+///
+/// Every hunk of the changes becomes its own `@@` hunk, with the context lines
+/// kept around the changed ones. Hunks that change nothing (when only
+/// searching, or when the replacement is the same as the match) are left out,
+/// and so is the whole file if none are left.
+pub fn unified_diff(
+    file_data: &FileData,
+    changes: &FileChanges,
+    starting_paths: &[&Path],
+) -> String {
+    let hunks = diff_hunks(file_data, changes);
+    match hunks.is_empty() {
+        true => hunks,
+        false => {
+            let diff_path = diff_path(&file_data.file_path, starting_paths);
+            format!("--- a/{}\n+++ b/{}\n{}", diff_path, diff_path, hunks)
+        }
+    }
+}
+
+/// Same as `unified_diff`, for a file that is also moved to `new_path`: a git
+/// diff that renames the file and changes its contents at once, which is the
+/// only way `git apply` moves the changes along with the file. A file whose
+/// contents do not change only gets the rename.
+pub fn moved_file_diff(
+    file_data: &FileData,
+    changes: &FileChanges,
+    new_path: &Path,
+    starting_paths: &[&Path],
+) -> String {
+    let hunks = diff_hunks(file_data, changes);
+    if hunks.is_empty() {
+        return rename_diff(&file_data.file_path, new_path, starting_paths);
+    }
+    let (from, to) = (
+        diff_path(&file_data.file_path, starting_paths),
+        diff_path(new_path, starting_paths),
+    );
+    format!(
+        "diff --git a/{} b/{}\nrename from {}\nrename to {}\n--- a/{}\n+++ b/{}\n{}",
+        from, to, from, to, from, to, hunks
+    )
+}
+
+fn diff_hunks(file_data: &FileData, changes: &FileChanges) -> String {
+    let mut hunks = String::new();
+
+    // the lines added (or removed) by the previous hunks move the new start
+    let mut line_count_change = 0isize;
+    changes.hunks.iter().for_each(|hunk| {
+        let mut body = String::new();
+        let mut old_count = 0;
+        let mut new_count = 0;
+        let mut changed = false;
+        hunk.lines.iter().for_each(|line| {
+            let old_nums = line.num..line.num + line.line_count;
+            old_count += line.line_count;
+            let new_text = line.new_text();
+            match line.has_term() && new_text != line.text {
+                true => {
+                    changed = true;
+                    old_nums.for_each(|num| {
+                        body.push_str(&format!(
+                            "-{}{}{}",
+                            bom_in_diff(file_data, num),
+                            file_data.contents[num],
+                            line_ending_in_diff(file_data, num)
+                        ))
+                    });
+                    let (new_lines, keeps_line_ending) = line.new_lines();
+                    new_count += new_lines.len();
+                    let last_num = line.num + line.line_count - 1;
+                    new_lines.iter().enumerate().for_each(|(index, new_line)| {
+                        let line_ending = match index + 1 == new_lines.len() {
+                            true if keeps_line_ending => line_ending_in_diff(file_data, last_num),
+                            true => NO_FINAL_NEWLINE,
+                            false => inner_line_ending(file_data, line.num),
+                        };
+                        let bom = match index {
+                            0 => bom_in_diff(file_data, line.num),
+                            _ => "",
+                        };
+                        body.push_str(&format!("+{}{}{}", bom, new_line, line_ending));
+                    });
+                }
+                false => {
+                    new_count += line.line_count;
+                    old_nums.for_each(|num| {
+                        body.push_str(&format!(
+                            " {}{}{}",
+                            bom_in_diff(file_data, num),
+                            file_data.contents[num],
+                            line_ending_in_diff(file_data, num)
+                        ))
+                    });
+                }
+            }
+        });
+
+        if !changed {
+            return;
+        }
+        let old_start = hunk.start() + 1;
+        let new_start = (old_start as isize + line_count_change) as usize;
+        line_count_change += new_count as isize - old_count as isize;
+        hunks.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n{}",
+            old_start, old_count, new_start, new_count, body
+        ));
+    });
+
+    hunks
+}
+
+/// Renders the move of a file whose contents do not change as a git rename.
+pub fn rename_diff(from: &Path, to: &Path, starting_paths: &[&Path]) -> String {
+    let (from, to) = (
+        diff_path(from, starting_paths),
+        diff_path(to, starting_paths),
+    );
+    format!(
+        "diff --git a/{} b/{}\nsimilarity index 100%\nrename from {}\nrename to {}\n",
+        from, to, from, to
+    )
+}
+
+/// The byte order mark is not part of the first line of the file data, but the
+/// patch has to have it to apply to the file.
+fn bom_in_diff(file_data: &FileData, line_num: usize) -> &'static str {
+    match file_data.bom && line_num == 0 {
+        true => UTF8_BOM,
+        false => "",
+    }
+}
+
+/// The line ending of the newlines a replacement adds to the line.
+fn inner_line_ending(file_data: &FileData, line_num: usize) -> &'static str {
+    match file_data.line_endings[line_num] {
+        LineEnding::None => LineEnding::Lf.as_str(),
+        line_ending => line_ending.as_str(),
+    }
+}
+
+const NO_FINAL_NEWLINE: &str = "\n\\ No newline at end of file\n";
+
+fn line_ending_in_diff(file_data: &FileData, line_num: usize) -> &'static str {
+    match file_data.line_endings[line_num] {
+        LineEnding::None => NO_FINAL_NEWLINE,
+        line_ending => line_ending.as_str(),
+    }
+}
+
+/// The path of the file in the diff, which is applied from the working
+/// directory like the path is relative to, unless the path cannot be applied
+/// from there (it is absolute or goes up with `..`). It is then relative to
+/// the starting path it was found under instead, or only the name of the file
+/// if that is the file itself.
+fn diff_path(file_path: &Path, starting_paths: &[&Path]) -> String {
+    let outside_working_dir = file_path
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir));
+    let file_path = match outside_working_dir {
+        true => {
+            let relative_path = dir_walker::relative_to_starting_path(file_path, starting_paths);
+            match relative_path.as_os_str().is_empty() {
+                true => file_path.file_name().map_or(file_path, Path::new),
+                false => relative_path,
+            }
+        }
+        false => file_path,
+    };
+    let file_path = file_path.strip_prefix(".").unwrap_or(file_path);
+    file_path
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::file_changes::{ContextLines, MatchOptions, WantedChanges};
+    use crate::test_dir::mock_wanted_changes;
+
+    fn diff_for_text(text: &str) -> String {
+        diff_for_replacement(text, "new")
+    }
+
+    fn diff_for_replacement(text: &str, new: &str) -> String {
+        let changes_requested = mock_wanted_changes("old", new);
+        let file_data = FileData::from_text(Path::new("./src/mock.rs"), text, &changes_requested)
+            .expect("text should contain the term");
+        let changes =
+            FileChanges::from_file_data(&file_data, &changes_requested, ContextLines::default());
+        unified_diff(&file_data, &changes, &[Path::new(".")])
+    }
+
+    fn diff_for(contents: Vec<&str>) -> String {
+        diff_for_text(&format!("{}\n", contents.join("\n")))
+    }
+
+    #[test]
+    fn diff_should_have_file_headers_and_hunk_header() {
+        let diff = diff_for(vec!["one", "two", "old three", "four", "five", "six"]);
+
+        assert_eq!(
+            diff,
+            "--- a/src/mock.rs\n+++ b/src/mock.rs\n\
+             @@ -1,5 +1,5 @@\n one\n two\n-old three\n+new three\n four\n five\n"
+        );
+    }
+
+    #[test]
+    fn separate_regions_should_have_separate_hunks() {
+        let mut contents = vec!["old first"];
+        contents.extend(vec!["filler"; 10]);
+        contents.push("old last");
+        let diff = diff_for(contents);
+
+        assert_eq!(diff.matches("@@ -").count(), 2);
+        assert!(diff.contains("@@ -1,3 +1,3 @@\n-old first\n+new first\n filler\n filler\n"));
+        assert!(diff.contains("@@ -10,3 +10,3 @@\n filler\n filler\n-old last\n+new last\n"));
+    }
+
+    #[test]
+    fn overlapping_regions_should_share_a_hunk() {
+        let diff = diff_for(vec!["old a", "b", "c", "old d"]);
+
+        assert_eq!(diff.matches("@@ -").count(), 1);
+        assert!(diff.contains("@@ -1,4 +1,4 @@\n"));
+    }
+
+    #[test]
+    fn line_endings_should_be_kept_in_the_diff() {
+        let diff = diff_for_text("a\r\nold b\r\n");
+
+        assert!(diff.ends_with(" a\r\n-old b\r\n+new b\r\n"));
+    }
+
+    #[test]
+    fn bom_should_be_kept_in_the_diff() {
+        let diff = diff_for_text("\u{feff}old a\nb\n");
+        assert!(diff.ends_with("\n-\u{feff}old a\n+\u{feff}new a\n b\n"));
+
+        let diff = diff_for_text("\u{feff}a\nold b\n");
+        assert!(diff.ends_with("\n \u{feff}a\n-old b\n+new b\n"));
+    }
+
+    #[test]
+    fn missing_final_newline_should_be_marked() {
+        let diff = diff_for_text("a\nold b");
+
+        assert!(diff.ends_with(
+            " a\n-old b\n\\ No newline at end of file\n+new b\n\\ No newline at end of file\n"
+        ));
+    }
+
+    #[test]
+    fn hunks_without_changes_should_be_left_out() {
+        let mut contents = vec!["old first"];
+        contents.extend(vec!["filler"; 10]);
+        contents.push("new last");
+        let text = format!("{}\n", contents.join("\n"));
+
+        let diff = diff_for_replacement(&text.replace("new last", "old last"), "old");
+        assert_eq!(diff, "");
+
+        let changes_requested = WantedChanges::new(
+            "(old|new)".to_string(),
+            "new".to_string(),
+            MatchOptions {
+                regex: true,
+                ..MatchOptions::default()
+            },
+        )
+        .unwrap();
+        let file_data =
+            FileData::from_text(Path::new("./src/mock.rs"), &text, &changes_requested).unwrap();
+        let changes =
+            FileChanges::from_file_data(&file_data, &changes_requested, ContextLines::default());
+        let diff = unified_diff(&file_data, &changes, &[Path::new(".")]);
+        assert_eq!(diff.matches("@@ -").count(), 1);
+        assert!(diff.contains("@@ -1,3 +1,3 @@\n-old first\n+new first\n"));
+    }
+
+    #[test]
+    fn multi_line_changes_should_move_the_start_of_the_next_hunks() {
+        let changes_requested = WantedChanges::new(
+            "use a;\nuse b;".to_string(),
+            "use {a, b};".to_string(),
+            MatchOptions {
+                multiline: true,
+                ..MatchOptions::default()
+            },
+        )
+        .unwrap();
+        let mut contents = vec!["use a;", "use b;"];
+        contents.extend(vec!["filler"; 10]);
+        contents.extend(vec!["use a;", "use b;"]);
+        let text = format!("{}\n", contents.join("\n"));
+        let file_data =
+            FileData::from_text(Path::new("./src/mock.rs"), &text, &changes_requested).unwrap();
+        let changes =
+            FileChanges::from_file_data(&file_data, &changes_requested, ContextLines::default());
+        let diff = unified_diff(&file_data, &changes, &[Path::new(".")]);
+
+        assert!(
+            diff.contains("@@ -1,4 +1,3 @@\n-use a;\n-use b;\n+use {a, b};\n filler\n filler\n")
+        );
+        assert!(
+            diff.contains("@@ -11,4 +10,3 @@\n filler\n filler\n-use a;\n-use b;\n+use {a, b};\n")
+        );
+    }
+
+    #[test]
+    fn removing_the_last_line_with_its_newline_should_add_no_line() {
+        let changes_requested = WantedChanges::new(
+            "use b;\n".to_string(),
+            "".to_string(),
+            MatchOptions {
+                multiline: true,
+                ..MatchOptions::default()
+            },
+        )
+        .unwrap();
+        let file_data = FileData::from_text(
+            Path::new("./src/mock.rs"),
+            "use a;\nuse b;\n",
+            &changes_requested,
+        )
+        .unwrap();
+        let changes =
+            FileChanges::from_file_data(&file_data, &changes_requested, ContextLines::default());
+
+        assert_eq!(
+            unified_diff(&file_data, &changes, &[Path::new(".")]),
+            "--- a/src/mock.rs\n+++ b/src/mock.rs\n@@ -1,2 +1,1 @@\n use a;\n-use b;\n"
+        );
+    }
+
+    #[test]
+    fn paths_outside_the_working_dir_should_be_relative_to_their_starting_path() {
+        let diff_paths = |file_path: &str, starting_path: &str| {
+            diff_path(
+                Path::new(file_path),
+                &[Path::new("src"), Path::new(starting_path)],
+            )
+        };
+
+        assert_eq!(diff_paths("./src/a.rs", "."), "src/a.rs");
+        assert_eq!(diff_paths("src/a.rs", "."), "src/a.rs");
+        assert_eq!(diff_paths("/tmp/p1/sub/b.txt", "/tmp/p1"), "sub/b.txt");
+        assert_eq!(diff_paths("../x/b.txt", "../x"), "b.txt");
+        assert_eq!(diff_paths("/tmp/p1/b.txt", "/tmp/p1/b.txt"), "b.txt");
+    }
+
+    #[test]
+    fn moved_files_should_have_their_hunks_under_the_rename() {
+        let changes_requested = mock_wanted_changes("old", "new");
+        let file_data =
+            FileData::from_text(Path::new("./src/old.rs"), "a\nold b\n", &changes_requested)
+                .unwrap();
+        let changes =
+            FileChanges::from_file_data(&file_data, &changes_requested, ContextLines::default());
+
+        assert_eq!(
+            moved_file_diff(
+                &file_data,
+                &changes,
+                Path::new("./src/new.rs"),
+                &[Path::new(".")]
+            ),
+            "diff --git a/src/old.rs b/src/new.rs\nrename from src/old.rs\nrename to src/new.rs\n\
+             --- a/src/old.rs\n+++ b/src/new.rs\n@@ -1,2 +1,2 @@\n a\n-old b\n+new b\n"
+        );
+    }
+
+    #[test]
+    fn renames_should_be_git_renames() {
+        assert_eq!(
+            rename_diff(
+                Path::new("./src/user.rs"),
+                Path::new("./src/account.rs"),
+                &[Path::new(".")]
+            ),
+            "diff --git a/src/user.rs b/src/account.rs\nsimilarity index 100%\n\
+             rename from src/user.rs\nrename to src/account.rs\n"
+        );
+    }
+}
@@ -1,12 +1,13 @@
 use crate::commons::unwrap_and_check_ok;
 use std::path::Path;
+use turbogrep::dir_walker::{get_file_paths_that_match_expr, PathFilter, WalkerOptions};
 
 #[test]
 fn should_read_files_from_src_dir() {
-    let expr = &"*.rs";
+    let expr = "*.rs";
     let init_path = Path::new(".");
     let files = unwrap_and_check_ok(
-        get_file_paths_that_match_expr(expr, &init_path),
+        get_file_paths_that_match_expr(&[expr], &[init_path], &WalkerOptions::default()),
         "result from parsing dirs should not be err",
     );
 
@@ -15,20 +16,48 @@ fn should_read_files_from_src_dir() {
 
 #[test]
 fn dirs_should_have_correct_pattern_match() {
-    let expr = &"*.rs";
+    let expr = "*.rs";
     let init_path = Path::new(".");
     let files = unwrap_and_check_ok(
-        get_file_paths_that_match_expr(expr, &init_path),
+        get_file_paths_that_match_expr(&[expr], &[init_path], &WalkerOptions::default()),
         "result from parsing dirs should not be err",
     );
 
     assert!(!files.is_empty());
 
-    let pattern = glob::Pattern::new(expr).unwrap();
+    // a glob without a `/` matches the names of the files
+    let pattern = globset::Glob::new(expr).unwrap().compile_matcher();
     files.into_iter().for_each(|path| {
         assert!(
-            pattern.matches_path(&path),
+            pattern.is_match(path.file_name().unwrap()),
             "paths of files received should match pattern"
         );
     });
 }
+
+#[test]
+fn invalid_pattern_should_be_err() {
+    let result =
+        get_file_paths_that_match_expr(&["["], &[Path::new(".")], &WalkerOptions::default());
+
+    assert!(result.is_err());
+}
+
+#[test]
+fn path_filter_should_match_like_the_walk() {
+    let starting_paths = [Path::new(".")];
+    let files = unwrap_and_check_ok(
+        get_file_paths_that_match_expr(&["src/*.rs"], &starting_paths, &WalkerOptions::default()),
+        "result from parsing dirs should not be err",
+    );
+    let filter = unwrap_and_check_ok(PathFilter::new(&["src/*.rs"]), "the glob should be valid");
+
+    assert!(!files.is_empty());
+    files.iter().for_each(|path| {
+        assert!(filter.matches(path, &starting_paths));
+    });
+    assert!(!filter.matches(Path::new("./tests/mod.rs"), &starting_paths));
+    // a file given as a starting path is matched by its name
+    let name_filter = PathFilter::new(&["lib.rs"]).unwrap();
+    assert!(name_filter.matches(Path::new("src/lib.rs"), &[Path::new("src/lib.rs")]));
+}
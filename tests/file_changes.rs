@@ -0,0 +1,50 @@
+use std::path::Path;
+use turbogrep::file_changes::{ContextLines, FileChanges, MatchOptions, WantedChanges};
+use turbogrep::file_io::{self, FileData};
+
+const TEXT: &str = "fn main() {\n    let user_id = 1;\n    println!(\"{}\", user_id);\n}";
+
+#[test]
+fn changes_should_be_computed_from_in_memory_text() {
+    let changes_requested = WantedChanges::new(
+        "user_id".to_string(),
+        "account_id".to_string(),
+        MatchOptions::default(),
+    )
+    .unwrap();
+    let file_data = FileData::from_text(Path::new("main.rs"), TEXT, &changes_requested)
+        .expect("text containing the term should have file data");
+    let changes =
+        FileChanges::from_file_data(&file_data, &changes_requested, ContextLines::default());
+
+    assert_eq!(file_data.term_containing_lines, vec![1, 2]);
+    assert_eq!(
+        file_io::apply_changes(&file_data, &changes),
+        TEXT.replace("user_id", "account_id")
+    );
+}
+
+#[test]
+fn text_without_the_term_should_have_no_file_data() {
+    let changes_requested = WantedChanges::new(
+        "nonexistent".to_string(),
+        "new".to_string(),
+        MatchOptions::default(),
+    )
+    .unwrap();
+
+    assert!(FileData::from_text(Path::new("main.rs"), TEXT, &changes_requested).is_none());
+}
+
+#[test]
+fn invalid_regex_should_be_err() {
+    assert!(WantedChanges::new(
+        "(".to_string(),
+        "new".to_string(),
+        MatchOptions {
+            regex: true,
+            ..MatchOptions::default()
+        }
+    )
+    .is_err());
+}
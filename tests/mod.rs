@@ -1,2 +1,3 @@
 mod commons;
 mod dir_walker;
+mod file_changes;